@@ -0,0 +1,170 @@
+//! Human-readable durations, shared by madsim and its macros.
+//!
+//! A duration is written as a number with a unit, e.g. `"500us"`, `"1ms"` or `"2.5s"`.
+
+use std::{ops::Range, time::Duration};
+
+/// Units and their length in nanoseconds.
+const UNITS: [(&str, u64); 7] = [
+    ("ns", 1),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("m", 60_000_000_000),
+    ("h", 3_600_000_000_000),
+];
+
+/// Parse a duration like `1ms` or `2.5s`.
+///
+/// The unit is one of `ns`, `us`, `µs`, `ms`, `s`, `m` and `h`.
+pub fn parse(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let i = (s.find(|c: char| !c.is_ascii_digit() && c != '.')).unwrap_or(s.len());
+    let (num, unit) = s.split_at(i);
+    let unit = unit.trim();
+    if s.is_empty() {
+        return Err("empty duration".into());
+    }
+    if unit.is_empty() {
+        return Err(format!("missing unit in duration {s:?}, e.g. \"{s}ms\""));
+    }
+    let scale = match UNITS.iter().find(|(u, _)| *u == unit) {
+        Some(&(_, scale)) => scale,
+        None => {
+            return Err(format!(
+                "unknown unit {unit:?} in duration {s:?}, expect one of ns, us, ms, s, m and h"
+            ))
+        }
+    };
+    let invalid = || format!("invalid duration {s:?}");
+    let (int, frac) = num.split_once('.').unwrap_or((num, ""));
+    if int.is_empty() && frac.is_empty() {
+        return Err(invalid());
+    }
+    let int = if int.is_empty() {
+        0
+    } else {
+        int.parse::<u64>().map_err(|_| invalid())?
+    };
+    let mut nanos = int.checked_mul(scale).ok_or_else(invalid)?;
+    let mut digit_scale = scale;
+    for c in frac.chars() {
+        let digit = c.to_digit(10).ok_or_else(invalid)? as u64;
+        digit_scale /= 10;
+        nanos = nanos.checked_add(digit * digit_scale).ok_or_else(invalid)?;
+    }
+    Ok(Duration::from_nanos(nanos))
+}
+
+/// Parse a duration like [`parse`], except that a number without unit is in seconds.
+///
+/// This is used by environment variables like `MADSIM_TEST_TIME_LIMIT`, which took
+/// seconds before, and the `time_limit` argument of `#[madsim::test]`.
+pub fn parse_or_secs(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return parse(&format!("{s}s"));
+    }
+    parse(s)
+}
+
+/// Format a duration in the largest unit not greater than it, e.g. `1ms` or `2.5s`.
+pub fn format(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    let (unit, scale) = [
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ]
+    .into_iter()
+    .find(|&(_, scale)| nanos >= scale)
+    .unwrap_or(("s", 1_000_000_000));
+    let (int, frac) = (nanos / scale, nanos % scale);
+    if frac == 0 {
+        return format!("{int}{unit}");
+    }
+    let width = scale.to_string().len() - 1;
+    let frac = format!("{frac:0width$}");
+    format!("{int}.{}{unit}", frac.trim_end_matches('0'))
+}
+
+/// Parse a range of durations like `1ms..10ms`.
+pub fn parse_range(s: &str) -> Result<Range<Duration>, String> {
+    match s.split_once("..") {
+        Some((start, end)) => Ok(parse(start)?..parse(end)?),
+        None => Err(format!("expect a range like \"1ms..10ms\", found {s:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Durations and their length in nanoseconds.
+    const VALID: &[(&str, u64)] = &[
+        ("0s", 0),
+        ("100ns", 100),
+        ("1.5us", 1_500),
+        ("3µs", 3_000),
+        ("1ms", 1_000_000),
+        ("2.5s", 2_500_000_000),
+        ("1.000001s", 1_000_001_000),
+        (" 2 m", 120_000_000_000),
+        (".5h", 1_800_000_000_000),
+    ];
+
+    /// Invalid durations.
+    const INVALID: &[&str] = &[
+        "",
+        "1",
+        "ms",
+        "1.2.3ms",
+        "-1ms",
+        "1d",
+        "99999999999h",
+        "18446744073.9s",
+    ];
+
+    /// Durations where a number without unit is in seconds, and their length in nanoseconds.
+    const VALID_OR_SECS: &[(&str, u64)] = &[
+        ("60", 60_000_000_000),
+        ("1.5", 1_500_000_000),
+        ("60s", 60_000_000_000),
+        (" 500ms ", 500_000_000),
+        ("2m", 120_000_000_000),
+    ];
+
+    /// Invalid durations where a number without unit is in seconds.
+    const INVALID_OR_SECS: &[&str] = &["", "1min", "s", "-1"];
+
+    #[test]
+    fn parse_with_default_unit() {
+        for &(s, nanos) in VALID_OR_SECS {
+            assert_eq!(parse_or_secs(s), Ok(Duration::from_nanos(nanos)), "{s:?}");
+        }
+        for s in INVALID_OR_SECS {
+            assert!(parse_or_secs(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_and_format() {
+        for &(s, nanos) in VALID {
+            assert_eq!(parse(s), Ok(Duration::from_nanos(nanos)), "{s:?}");
+        }
+        for s in INVALID {
+            assert!(parse(s).is_err(), "{s:?}");
+        }
+        for s in ["0s", "100ns", "1.5us", "1ms", "2.5s", "1.000001s"] {
+            assert_eq!(format(parse(s).unwrap()), s);
+        }
+        assert_eq!(format(Duration::from_secs(120)), "120s");
+        assert_eq!(
+            parse_range("1ms..10ms"),
+            Ok(Duration::from_millis(1)..Duration::from_millis(10))
+        );
+        assert!(parse_range("1ms").is_err());
+    }
+}
@@ -3,6 +3,7 @@
 mod request;
 mod service;
 
+use darling::FromMeta;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::DeriveInput;
@@ -32,6 +33,12 @@ pub fn service(args: TokenStream, input: TokenStream) -> TokenStream {
 ///     println!("Hello world");
 /// }
 /// ```
+///
+/// The function can return a value, e.g. `Result<(), E>`, which is returned
+/// from `main` as is.
+///
+/// The runtime can be configured by arguments and environment variables.
+/// See [`test`](macro@test) for details.
 #[proc_macro_attribute]
 pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::ItemFn);
@@ -50,40 +57,139 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
 /// }
 /// ```
 ///
+/// The test can also return `Result<(), E>` where `E: Debug`. An error is
+/// reported like a panic, along with the seed to reproduce it.
+///
+/// ```ignore
+/// #[madsim::test]
+/// async fn my_test() -> std::io::Result<()> {
+///     madsim::net::Endpoint::bind("10.0.0.1:1").await?;
+///     Ok(())
+/// }
+/// ```
+///
+/// Any other return type, e.g. `ExitCode`, is returned as is.
+///
+/// # Arguments
+///
+/// Test can be configured by the following arguments:
+///
+/// ```ignore
+/// #[madsim::test(
+///     seed = 42,
+///     count = 100,
+///     jobs = 4,
+///     time_limit = "60s",
+///     config = "tests/net.toml",
+///     simulators(my_crate::DiskSim),
+///     check_determinism
+/// )]
+/// async fn my_test() {}
+/// ```
+///
+/// - `seed`: The random seed for test.
+/// - `count`: The number of tests.
+/// - `jobs`: The number of jobs to run simultaneously.
+/// - `time_limit`: The time limit for the test, e.g. `"500ms"`, `"60s"` or `"1.5m"`.
+///   A number without unit is in seconds.
+/// - `config`: The config file path, relative to the crate root.
+/// - `simulators`: Custom simulators to register to the runtime.
+/// - `check_determinism`: Enable determinism check.
+///
+/// The environment variables below override the arguments.
+///
 /// # Configuration
 ///
 /// Test can be configured using the following environment variables:
 ///
 /// - `MADSIM_TEST_SEED`: Set the random seed for test.
 ///
-///     By default, the seed is set to the seconds since the Unix epoch.
+///   By default, the seed is set to the seconds since the Unix epoch.
 ///
 /// - `MADSIM_TEST_NUM`: Set the number of tests.
 ///
-///     The seed will increase by 1 for each test.
+///   The seed will increase by 1 for each test.
 ///
-///     By default, the number is 1.
+///   By default, the number is 1.
 ///
 /// - `MADSIM_TEST_JOBS`: Set the number of jobs to run simultaneously.
 ///
-///     By default, the number of jobs is 1.
+///   By default, the number of jobs is 1.
 ///
-/// - `MADSIM_TEST_CONFIG`: Set the config file path.
+/// - `MADSIM_TEST_CONFIG`: Set the config file path. See `madsim::Config` for the format.
 ///
-///     By default, tests will use the default configuration.
+///   By default, tests will use the default configuration.
 ///
-/// - `MADSIM_TEST_TIME_LIMIT`: Set the time limit for the test.
+/// - `MADSIM_TEST_TIME_LIMIT`: Set the time limit for the test, e.g. `60s` or `500ms`.
 ///
-///     The test will panic if time limit exceeded in the simulation.
+///   A number without unit is in seconds.
+///   The test will panic if time limit exceeded in the simulation.
 ///
-///     By default, there is no time limit.
+///   By default, there is no time limit.
 ///
 /// - `MADSIM_TEST_CHECK_DETERMINISM`: Enable determinism check.
 ///
-///     The test will be run at least twice with the same seed.
-///     If any non-determinism detected, it will panic as soon as possible.
+///   The test will be run at least twice with the same seed.
+///   If any non-determinism detected, it will panic as soon as possible.
+///
+///   If the value is `full`, all events in the simulation are recorded,
+///   and the diverging event is reported along with the last common events.
+///
+///   By default, it is disabled.
+///
+/// - `MADSIM_TEST_SCHEDULER`: Set the task scheduler.
+///
+///   The value can be `random`, `fifo` or `pct:depth=<d>,steps=<k>`.
+///
+///   By default, the scheduler in the config is used.
+///
+/// - `MADSIM_TEST_RECORD`: Record nondeterministic decisions to the file.
+///
+///   If the number of tests is greater than 1, the seed will be appended
+///   to the file name.
+///
+///   By default, it is disabled.
+///
+/// - `MADSIM_TEST_REPLAY`: Replay nondeterministic decisions from the file.
+///
+///   If the running code diverges from the trace, a warning will be printed.
+///
+///   By default, it is disabled.
+///
+/// - `MADSIM_TEST_STRICT`: Set the strict mode to detect escapes from the simulation.
+///
+///   The value can be `off`, `warn` or `panic`.
+///
+///   By default, the strict mode in the config is used.
+///
+/// - `MADSIM_TEST_ISOLATE`: Run each seed in a separate process.
+///
+///   The test binary is re-executed for each seed, so an abort, stack overflow,
+///   `process::exit` or leaked global state in one seed doesn't affect the others.
+///   All failing seeds are reported with their exit causes.
+///
+///   By default, it is disabled.
+///
+/// - `MADSIM_TEST_KEEP_GOING`: Keep running after a seed fails.
 ///
-///     By default, it is disabled.
+///   At the end, a summary is printed with the number of passed and failed seeds,
+///   the failures grouped by panic message with the lowest reproducing seed,
+///   and statistics of the simulated time.
+///
+///   By default, it is disabled and the test panics on the first failure.
+///
+/// - `MADSIM_TEST_REPORT`: Write the summary as JSON to the file.
+///
+///   This implies `MADSIM_TEST_KEEP_GOING`.
+///
+///   By default, it is disabled.
+///
+/// - `MADSIM_BREAK_AT`: Stop when the simulated time passes the value, e.g. `12.345s`.
+///
+///   The state of all nodes and live tasks is printed to stderr.
+///   If a debugger is attached, `SIGTRAP` is raised to break into it.
+///
+///   By default, it is disabled.
 #[proc_macro_attribute]
 pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::ItemFn);
@@ -112,9 +218,22 @@ pub fn tokio_test(args: TokenStream, item: TokenStream) -> TokenStream {
     parse(input, args, true, true).unwrap_or_else(|e| e.to_compile_error().into())
 }
 
+/// Arguments of `#[madsim::main]` and `#[madsim::test]`.
+#[derive(Debug, Default, FromMeta)]
+#[darling(default)]
+struct Args {
+    seed: Option<u64>,
+    count: Option<u64>,
+    jobs: Option<u16>,
+    time_limit: Option<syn::LitStr>,
+    config: Option<String>,
+    simulators: darling::util::PathList,
+    check_determinism: bool,
+}
+
 fn parse(
     mut input: syn::ItemFn,
-    _args: syn::AttributeArgs,
+    args: syn::AttributeArgs,
     is_test: bool,
     is_tokio: bool,
 ) -> Result<TokenStream, syn::Error> {
@@ -122,6 +241,10 @@ fn parse(
         let msg = "the `async` keyword is missing from the function declaration";
         return Err(syn::Error::new_spanned(input.sig.fn_token, msg));
     }
+    let args = match Args::from_list(&args) {
+        Ok(args) => args,
+        Err(e) => return Ok(e.write_errors().into()),
+    };
 
     let body = &input.block;
     let brace_token = input.block.brace_token;
@@ -130,10 +253,71 @@ fn parse(
     } else {
         quote! {}
     };
+    let mut settings = vec![];
+    if let Some(seed) = args.seed {
+        settings.push(quote! { builder.seed = #seed; });
+    }
+    if let Some(count) = args.count {
+        settings.push(quote! { builder.count = #count; });
+    }
+    if let Some(jobs) = args.jobs {
+        settings.push(quote! { builder.jobs = #jobs; });
+    }
+    if let Some(time_limit) = args.time_limit {
+        let nanos = madsim_duration::parse_or_secs(&time_limit.value())
+            .map_err(|e| syn::Error::new_spanned(&time_limit, e))?
+            .as_nanos() as u64;
+        settings.push(quote! {
+            builder.time_limit = ::core::option::Option::Some(
+                ::core::time::Duration::from_nanos(#nanos)
+            );
+        });
+    }
+    if let Some(config) = args.config {
+        settings.push(quote! {
+            let path = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(#config);
+            builder.config = ::std::fs::read_to_string(&path)
+                .expect("failed to read config file")
+                .parse()
+                .unwrap_or_else(|e| panic!("invalid config file {:?}: {}", path, e));
+        });
+    }
+    for sim in args.simulators.iter() {
+        settings.push(quote! { builder = builder.add_simulator::<#sim>(); });
+    }
+    if args.check_determinism {
+        settings.push(quote! { builder.check = true; });
+    }
+    let name = input.sig.ident.to_string();
+    let returns_result = match &input.sig.output {
+        syn::ReturnType::Type(_, ty) if is_test => is_unit_result(ty)?,
+        _ => false,
+    };
+    let run = match &input.sig.output {
+        syn::ReturnType::Type(_, ty) if returns_result => {
+            // the test returns `Result<(), E>`.
+            // turn the error into a panic in the simulation, so that it is reported with the seed.
+            quote! {
+                builder.with_env().run_test(|| async {
+                    let ret: #ty = async #body.await;
+                    if let ::core::result::Result::Err(e) = ret {
+                        ::core::panic!("`{}` returned an error: {:?}", #name, e);
+                    }
+                });
+                ::core::result::Result::Ok(())
+            }
+        }
+        syn::ReturnType::Type(_, ty) if !is_unit(ty) => {
+            quote! { builder.with_env().run(|| async #body) }
+        }
+        _ => quote! { builder.with_env().run_test(|| async #body) },
+    };
     input.block = syn::parse2(quote! {
         {
             #tokio::madsim::runtime::init_logger();
-            #tokio::madsim::runtime::Builder::from_env().run(|| async #body)
+            let mut builder = #tokio::madsim::runtime::Builder::new();
+            #(#settings)*
+            #run
         }
     })
     .expect("Parsing failure");
@@ -151,3 +335,55 @@ fn parse(
     };
     Ok(result.into())
 }
+
+/// Returns true if the type is `()`.
+fn is_unit(ty: &syn::Type) -> bool {
+    matches!(ty, syn::Type::Tuple(t) if t.elems.is_empty())
+}
+
+/// Returns true if the type is a path ending in `Result` with `()` as the first argument,
+/// e.g. `io::Result<()>`, or false if it doesn't end in `Result`.
+///
+/// Returns an error for other `Result`s, whose `Ok` value can not be returned from a test.
+fn is_unit_result(ty: &syn::Type) -> Result<bool, syn::Error> {
+    let segment = match ty {
+        syn::Type::Path(p) => match p.path.segments.last() {
+            Some(s) if s.ident == "Result" => s,
+            _ => return Ok(false),
+        },
+        _ => return Ok(false),
+    };
+    let ok = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.first(),
+        _ => None,
+    };
+    match ok {
+        Some(syn::GenericArgument::Type(ok)) if is_unit(ok) => Ok(true),
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            "a test can only return `Result<(), E>` where `E: Debug`",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `test` is shadowed by the attribute defined in this crate
+    #[::core::prelude::v1::test]
+    fn unit_result() {
+        let ty: syn::Type = syn::parse_quote!(Result<(), String>);
+        assert!(is_unit_result(&ty).unwrap());
+        let ty: syn::Type = syn::parse_quote!(std::io::Result<()>);
+        assert!(is_unit_result(&ty).unwrap());
+        let ty: syn::Type = syn::parse_quote!(std::process::ExitCode);
+        assert!(!is_unit_result(&ty).unwrap());
+        let ty: syn::Type = syn::parse_quote!(Result<u32, String>);
+        assert!(is_unit_result(&ty).is_err());
+        let ty: syn::Type = syn::parse_quote!(MyResult);
+        assert!(!is_unit_result(&ty).unwrap());
+        let ty: syn::Type = syn::parse_quote!(Result);
+        assert!(is_unit_result(&ty).is_err());
+    }
+}
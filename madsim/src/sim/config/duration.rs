@@ -0,0 +1,48 @@
+//! Human-readable durations in configurations.
+//!
+//! A duration is written as a number with a unit, e.g. `"500us"`, `"1ms"` or `"2.5s"`.
+//! The form of serde, `{ secs = 1, nanos = 0 }`, is also accepted.
+
+pub(crate) use madsim_duration::{format, parse, parse_or_secs, parse_range};
+use serde::{
+    de::{self, value::MapAccessDeserializer, value::SeqAccessDeserializer, Visitor},
+    Deserialize, Deserializer, Serializer,
+};
+use std::{fmt, time::Duration};
+
+/// Serialize a duration as a string like `1ms`.
+pub(crate) fn serialize<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(*duration))
+}
+
+/// Deserialize a duration from a string like `1ms`, or the form of serde.
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    deserializer.deserialize_any(DurationVisitor)
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a duration like \"1ms\"")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        parse(v).map_err(E::custom)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        Duration::deserialize(MapAccessDeserializer::new(map))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        Duration::deserialize(SeqAccessDeserializer::new(seq))
+    }
+}
@@ -0,0 +1,78 @@
+//! Config error types.
+
+use std::fmt;
+
+/// Error returned when parsing or validating a [`Config`](crate::Config).
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The TOML is malformed, or a value has a wrong type.
+    ///
+    /// The message contains the key and the position of the value.
+    Parse(toml::de::Error),
+    /// A value is out of range.
+    Invalid {
+        /// The full key of the value, e.g. `net.links[0].packet_loss_rate`.
+        key: String,
+        /// Why the value is invalid.
+        message: String,
+    },
+}
+
+impl ConfigError {
+    pub(crate) fn invalid(key: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Returns the key of the invalid value.
+    ///
+    /// Returns `None` for parse errors, whose key is only in the message.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ConfigError::Parse(_) => None,
+            ConfigError::Invalid { key, .. } => Some(key),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Parse(e) => write!(f, "failed to parse config: {e}"),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "invalid value for `{key}`: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+/// Check that a possibility is in `[0, 1]`.
+pub(crate) fn check_probability(key: &str, value: f64) -> Result<(), ConfigError> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(ConfigError::invalid(
+            key,
+            format!("{value} is not a possibility in [0, 1]"),
+        ))
+    }
+}
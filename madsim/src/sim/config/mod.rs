@@ -0,0 +1,353 @@
+//! Simulation configuration.
+
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+pub use self::error::ConfigError;
+use crate::{
+    net::{self, tcp},
+    strict, task,
+};
+use ahash::AHasher;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub(crate) mod duration;
+pub(crate) mod error;
+mod schema;
+
+/// Simulation configuration.
+///
+/// It is usually parsed from a TOML file given by `MADSIM_TEST_CONFIG` or the
+/// `config` argument of `#[madsim::test]`. Durations are written as `"1ms"` or
+/// `"2.5s"`, and ranges of durations as `"1ms..10ms"`. The latency can also be a
+/// distribution, see [`net::Latency`]. Bandwidths are in bytes per second. All
+/// sections and keys are optional. For example:
+///
+/// ```
+/// use madsim::Config;
+///
+/// let config: Config = r#"
+///     scheduler = "random"
+///
+///     [net]
+///     packet_loss_rate = 0.001
+///     send_latency = "1ms..10ms"
+///     bandwidth = 125000000
+///     nic_bandwidth = 1250000000
+///     queue_capacity = 1000000
+///
+///     [[net.links]]
+///     between = ["us-east", "eu-west"]
+///     send_latency = { type = "log_normal", median = "80ms", sigma = 0.1 }
+///
+///     [strict]
+///     mode = "warn"
+///     allow = ["getaddrinfo"]
+///
+///     [simulators.raft]
+///     election_timeout_ms = 150
+/// "#
+/// .parse()
+/// .unwrap();
+/// assert_eq!(config.to_string().parse::<Config>().unwrap(), config);
+///
+/// let err = "[net]\npacket_loss_rate = 1.5".parse::<Config>().unwrap_err();
+/// assert_eq!(err.key(), Some("net.packet_loss_rate"));
+/// ```
+///
+/// See [`schema`](Config::schema) for the JSON Schema.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Hash, Clone)]
+pub struct Config {
+    /// Network configurations.
+    #[serde(default)]
+    pub net: net::Config,
+
+    /// Tcp Configurations
+    #[serde(default)]
+    pub tcp: tcp::TcpConfig,
+
+    /// Task scheduler.
+    #[serde(default)]
+    pub scheduler: task::SchedulerConfig,
+
+    /// Strict mode.
+    #[serde(default)]
+    pub strict: strict::StrictConfig,
+
+    /// Configurations of custom simulators, in the `[simulators.<name>]` sections.
+    #[serde(default, skip_serializing_if = "SimulatorConfigs::is_empty")]
+    pub simulators: SimulatorConfigs,
+}
+
+impl Config {
+    /// Returns the hash value of this config.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = AHasher::new_with_keys(0, 0);
+        Hash::hash(self, &mut hasher);
+        hasher.finish()
+    }
+
+    /// Check that all values are in range.
+    ///
+    /// It is called when parsing a config and when creating a runtime.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.net.validate("net")?;
+        if let task::SchedulerConfig::Pct { depth, steps } = self.scheduler {
+            if depth == 0 {
+                return Err(ConfigError::invalid(
+                    "scheduler.pct.depth",
+                    "should be positive",
+                ));
+            }
+            if steps == 0 {
+                return Err(ConfigError::invalid(
+                    "scheduler.pct.steps",
+                    "should be positive",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the [JSON Schema](https://json-schema.org) of the TOML config.
+    ///
+    /// Editors can use it to complete and check config files. For example, with
+    /// [Taplo](https://taplo.tamasfe.dev), save the schema to a file and add
+    /// `#:schema ./madsim.schema.json` at the top of the config.
+    pub fn schema() -> String {
+        serde_json::to_string_pretty(&schema::schema()).unwrap()
+    }
+
+    /// Deserialize the config section of a custom simulator.
+    ///
+    /// Returns the default value if the section `[simulators.<name>]` doesn't exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::Config;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, Default, PartialEq, Deserialize)]
+    /// struct RaftConfig {
+    ///     election_timeout_ms: u64,
+    /// }
+    ///
+    /// let config: Config = r#"
+    ///     [simulators.raft]
+    ///     election_timeout_ms = 150
+    /// "#
+    /// .parse()
+    /// .unwrap();
+    /// let raft: RaftConfig = config.simulator("raft").unwrap();
+    /// assert_eq!(raft.election_timeout_ms, 150);
+    /// assert_eq!(config.simulator::<RaftConfig>("disk").unwrap(), Default::default());
+    /// ```
+    pub fn simulator<T: DeserializeOwned + Default>(
+        &self,
+        name: &str,
+    ) -> Result<T, toml::de::Error> {
+        match self.simulators.0.get(name) {
+            Some(value) => value.clone().try_into(),
+            None => Ok(T::default()),
+        }
+    }
+
+    /// Set the config section of a custom simulator.
+    pub fn set_simulator<T: Serialize>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), toml::ser::Error> {
+        let value = toml::Value::try_from(value)?;
+        self.simulators.0.insert(name.into(), value);
+        Ok(())
+    }
+}
+
+/// Configurations of custom simulators by name.
+///
+/// Use [`Config::simulator`] to get the section of a simulator.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(transparent)]
+pub struct SimulatorConfigs(BTreeMap<String, toml::Value>);
+
+impl SimulatorConfigs {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Hash for SimulatorConfigs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (name, value) in &self.0 {
+            name.hash(state);
+            hash_value(value, state);
+        }
+    }
+}
+
+/// Hash a TOML value, which doesn't implement `Hash` because of floats.
+fn hash_value<H: Hasher>(value: &toml::Value, state: &mut H) {
+    std::mem::discriminant(value).hash(state);
+    match value {
+        toml::Value::String(s) => s.hash(state),
+        toml::Value::Integer(i) => i.hash(state),
+        toml::Value::Float(f) => f.to_bits().hash(state),
+        toml::Value::Boolean(b) => b.hash(state),
+        toml::Value::Datetime(d) => d.to_string().hash(state),
+        toml::Value::Array(array) => {
+            array.len().hash(state);
+            for value in array {
+                hash_value(value, state);
+            }
+        }
+        toml::Value::Table(table) => {
+            table.len().hash(state);
+            for (key, value) in table {
+                key.hash(state);
+                hash_value(value, state);
+            }
+        }
+    }
+}
+
+/// Parse a config from TOML and validate it.
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Config = toml::from_str(s)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Print the config into TOML.
+impl ToString for Config {
+    fn to_string(&self) -> String {
+        // convert to a value first, which puts tables after other values
+        let value = toml::Value::try_from(self).unwrap();
+        toml::to_string_pretty(&value).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parse() {
+        let config: Config = r#"
+        [net]
+        packet_loss_rate = 0.1
+        send_latency = "1ms..10ms"
+        
+        [tcp]
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                net: net::Config {
+                    packet_loss_rate: 0.1,
+                    send_latency: (Duration::from_millis(1)..Duration::from_millis(10)).into(),
+                    ..Default::default()
+                },
+                tcp: tcp::TcpConfig {},
+                scheduler: task::SchedulerConfig::Random,
+                strict: Default::default(),
+                simulators: Default::default(),
+            }
+        );
+
+        // the form of serde is still accepted
+        let config1: Config = r#"
+        [net]
+        packet_loss_rate = 0.1
+        send_latency = { start = { secs = 0, nanos = 1000000 }, end = { secs = 0, nanos = 10000000 } }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config1, config);
+        assert_eq!(config.to_string().parse::<Config>().unwrap(), config);
+        assert_eq!(
+            Config::default().to_string().parse::<Config>().unwrap(),
+            Config::default()
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = "[net]\nsend_latency = \"1ms..\""
+            .parse::<Config>()
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(_)));
+        assert!(err.to_string().contains("net.send_latency"), "{err}");
+
+        let err = r#"
+        [[net.links]]
+        between = ["a", "b"]
+        bandwidth = 0
+        "#
+        .parse::<Config>()
+        .unwrap_err();
+        assert_eq!(err.key(), Some("net.links[0].bandwidth"));
+
+        let err = "scheduler = { pct = { depth = 0 } }"
+            .parse::<Config>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value for `scheduler.pct.depth`: should be positive"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid value for `net.packet_loss_rate`")]
+    fn invalid_runtime_config() {
+        let mut config = Config::default();
+        config.net.packet_loss_rate = 1.5;
+        crate::runtime::Runtime::with_seed_and_config(1, config);
+    }
+
+    #[test]
+    fn simulators() {
+        #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct DiskConfig {
+            latency_us: u64,
+            fail_rate: f64,
+        }
+
+        let config: Config = r#"
+        [simulators.disk]
+        latency_us = 100
+        fail_rate = 0.5
+        "#
+        .parse()
+        .unwrap();
+        let disk = DiskConfig {
+            latency_us: 100,
+            fail_rate: 0.5,
+        };
+        assert_eq!(config.simulator::<DiskConfig>("disk").unwrap(), disk);
+        assert_eq!(
+            config.simulator::<DiskConfig>("other").unwrap(),
+            DiskConfig::default()
+        );
+        assert!(config.simulator::<Vec<u8>>("disk").is_err());
+
+        let mut config1 = Config::default();
+        config1.set_simulator("disk", &disk).unwrap();
+        assert_eq!(config1, config);
+        assert_eq!(config1.hash(), config.hash());
+        assert_ne!(Config::default().hash(), config.hash());
+    }
+}
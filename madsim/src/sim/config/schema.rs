@@ -0,0 +1,382 @@
+//! JSON Schema of the config.
+
+use serde_json::{json, Map, Value};
+
+/// Returns the JSON Schema of [`Config`](super::Config).
+pub(super) fn schema() -> Value {
+    let link = link_properties();
+    let mut rule = link.clone();
+    rule.insert(
+        "between".into(),
+        json!({
+            "description": "The names of two zones or regions.",
+            "type": "array",
+            "items": { "type": "string" },
+            "minItems": 2,
+            "maxItems": 2
+        }),
+    );
+    let mut net = link;
+    net.insert(
+        "nic_bandwidth".into(),
+        json!({
+            "description": "The bandwidth of the network interface of each node in bytes per second.",
+            "type": "integer",
+            "minimum": 1
+        }),
+    );
+    net.insert(
+        "queue_capacity".into(),
+        json!({
+            "description": "The capacity in bytes of the queue of each network interface and link.",
+            "type": "integer",
+            "minimum": 0
+        }),
+    );
+    net.insert(
+        "links".into(),
+        json!({
+            "description": "Link configurations between zones or regions.",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": rule,
+                "required": ["between"],
+                "additionalProperties": false
+            }
+        }),
+    );
+    let spike = json!({ "$ref": "#/definitions/spike" });
+    let probability = json!({
+        "description": "The possibility of a spike.",
+        "type": "number",
+        "minimum": 0,
+        "maximum": 1
+    });
+    let mut latency = vec![
+        json!({
+            "description": "A uniform distribution, e.g. \"1ms..10ms\".",
+            "type": "string",
+            "pattern": "\\.\\."
+        }),
+        json!({ "$ref": "#/definitions/duration" }),
+        json!({
+            "description": "A uniform distribution.",
+            "type": "object",
+            "properties": {
+                "start": { "$ref": "#/definitions/duration" },
+                "end": { "$ref": "#/definitions/duration" },
+                "spike": spike
+            },
+            "required": ["start", "end"],
+            "additionalProperties": false
+        }),
+    ];
+    latency.extend(distributions(("spike", spike), None));
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "madsim config",
+        "type": "object",
+        "properties": {
+            "net": {
+                "description": "Network configurations.",
+                "type": "object",
+                "properties": net,
+                "additionalProperties": false
+            },
+            "tcp": {
+                "description": "TCP configurations.",
+                "type": "object"
+            },
+            "scheduler": {
+                "description": "The task scheduler.",
+                "anyOf": [
+                    {
+                        "description": "A scheduler like \"random\", \"fifo\" or \"pct:depth=3,steps=10000\".",
+                        "type": "string",
+                        "pattern": "^(random|fifo|pct(:(depth|steps)=\\d+(,(depth|steps)=\\d+)*)?)$"
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "pct": {
+                                "type": "object",
+                                "properties": {
+                                    "depth": { "type": "integer", "minimum": 1 },
+                                    "steps": { "type": "integer", "minimum": 1 }
+                                },
+                                "additionalProperties": false
+                            }
+                        },
+                        "required": ["pct"],
+                        "additionalProperties": false
+                    }
+                ]
+            },
+            "strict": {
+                "description": "Strict mode.",
+                "type": "object",
+                "properties": {
+                    "mode": { "enum": ["off", "warn", "panic"] },
+                    "allow": { "type": "array", "items": { "type": "string" } }
+                },
+                "additionalProperties": false
+            },
+            "simulators": {
+                "description": "Configurations of custom simulators by name.",
+                "type": "object",
+                "additionalProperties": { "type": "object" }
+            }
+        },
+        "additionalProperties": false,
+        "definitions": {
+            "duration": {
+                "anyOf": [
+                    {
+                        "description": "A duration like \"1ms\" or \"2.5s\".",
+                        "type": "string",
+                        "pattern": "^\\s*(\\d+\\.?\\d*|\\.\\d+)\\s*(ns|us|µs|ms|s|m|h)\\s*$"
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "secs": { "type": "integer", "minimum": 0 },
+                            "nanos": { "type": "integer", "minimum": 0, "maximum": 999999999 }
+                        },
+                        "required": ["secs", "nanos"],
+                        "additionalProperties": false
+                    }
+                ]
+            },
+            "latency": {
+                "description": "The latency of sending packets.",
+                "anyOf": latency
+            },
+            "spike": {
+                "description": "A rare extra delay added to the latency.",
+                "anyOf": distributions(("probability", probability), Some("probability"))
+            }
+        }
+    })
+}
+
+/// Returns the properties of a link.
+fn link_properties() -> Map<String, Value> {
+    let properties = json!({
+        "packet_loss_rate": {
+            "description": "Possibility of packet loss.",
+            "type": "number",
+            "minimum": 0,
+            "maximum": 1
+        },
+        "send_latency": { "$ref": "#/definitions/latency" },
+        "bandwidth": {
+            "description": "The bandwidth of each link in bytes per second.",
+            "type": "integer",
+            "minimum": 1
+        }
+    });
+    match properties {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the schemas of distributions selected by `type`, each with an extra property.
+fn distributions(extra: (&str, Value), required: Option<&str>) -> Vec<Value> {
+    let duration = json!({ "$ref": "#/definitions/duration" });
+    let variants = [
+        ("constant", json!({ "value": duration })),
+        ("uniform", json!({ "start": duration, "end": duration })),
+        ("normal", json!({ "mean": duration, "std_dev": duration })),
+        (
+            "log_normal",
+            json!({ "median": duration, "sigma": { "type": "number", "minimum": 0 } }),
+        ),
+        (
+            "pareto",
+            json!({ "scale": duration, "shape": { "type": "number", "exclusiveMinimum": 0 } }),
+        ),
+        (
+            "histogram",
+            json!({
+                "buckets": {
+                    "description": "Pairs of latency in microseconds and the number of samples.",
+                    "type": "array",
+                    "items": {
+                        "type": "array",
+                        "items": { "type": "integer", "minimum": 0 },
+                        "minItems": 2,
+                        "maxItems": 2
+                    }
+                },
+                "path": {
+                    "description": "A file with a pair of latency in microseconds and count on each line.",
+                    "type": "string"
+                }
+            }),
+        ),
+    ];
+    (variants.into_iter())
+        .map(|(name, params)| {
+            let mut properties = match params {
+                Value::Object(map) => map,
+                _ => unreachable!(),
+            };
+            let mut required = (properties.keys())
+                .filter(|key| !matches!(key.as_str(), "buckets" | "path"))
+                .cloned()
+                .chain(required.map(String::from))
+                .collect::<Vec<_>>();
+            required.push("type".into());
+            properties.insert("type".into(), json!({ "const": name }));
+            properties.insert(extra.0.into(), extra.1.clone());
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+
+    #[test]
+    fn keys() {
+        let schema: Value = serde_json::from_str(&Config::schema()).unwrap();
+        let properties = &schema["properties"];
+        let config: Config = r#"
+        scheduler = { pct = { depth = 3 } }
+        [[net.links]]
+        between = ["a", "b"]
+        [simulators.raft]
+        "#
+        .parse()
+        .unwrap();
+        // all keys of the config are in the schema
+        let value = serde_json::to_value(&config).unwrap();
+        for (key, value) in value.as_object().unwrap() {
+            assert!(properties[key].is_object(), "{key}");
+            if key == "net" {
+                for key in value.as_object().unwrap().keys() {
+                    assert!(
+                        properties["net"]["properties"][key].is_object(),
+                        "net.{key}"
+                    );
+                }
+            }
+        }
+        let rule = &properties["net"]["properties"]["links"]["items"]["properties"];
+        for key in value["net"]["links"][0].as_object().unwrap().keys() {
+            assert!(rule[key].is_object(), "net.links.{key}");
+        }
+        for key in ["bandwidth", "nic_bandwidth", "queue_capacity"] {
+            assert!(
+                properties["net"]["properties"][key].is_object(),
+                "net.{key}"
+            );
+        }
+        assert_eq!(
+            schema["definitions"]["spike"]["anyOf"]
+                .as_array()
+                .unwrap()
+                .len(),
+            6
+        );
+    }
+
+    /// Returns the errors of validating the TOML against the schema.
+    fn validate(toml: &str) -> Vec<String> {
+        let schema: Value = serde_json::from_str(&Config::schema()).unwrap();
+        let schema = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let value: Value = toml::from_str(toml).unwrap();
+        let errors = match schema.validate(&value) {
+            Ok(()) => vec![],
+            Err(errors) => errors
+                .map(|e| format!("{}: {e}", e.instance_path))
+                .collect(),
+        };
+        errors
+    }
+
+    #[test]
+    fn validate_configs() {
+        // the example in the doc of `Config`
+        let example = r#"
+        scheduler = "random"
+
+        [net]
+        packet_loss_rate = 0.001
+        send_latency = "1ms..10ms"
+        bandwidth = 125000000
+        nic_bandwidth = 1250000000
+        queue_capacity = 1000000
+
+        [[net.links]]
+        between = ["us-east", "eu-west"]
+        send_latency = { type = "log_normal", median = "80ms", sigma = 0.1 }
+
+        [strict]
+        mode = "warn"
+        allow = ["getaddrinfo"]
+
+        [simulators.raft]
+        election_timeout_ms = 150
+        "#;
+        assert_eq!(validate(example), Vec::<String>::new());
+        let config = example.parse::<Config>().unwrap();
+        assert_eq!(validate(&config.to_string()), Vec::<String>::new());
+        assert_eq!(
+            validate(&Config::default().to_string()),
+            Vec::<String>::new()
+        );
+
+        // all kinds of latency and scheduler, as written by hand and by `to_string`
+        let config = r#"
+        scheduler = { pct = { depth = 2, steps = 100 } }
+
+        [net]
+        send_latency = { start = { secs = 0, nanos = 1000 }, end = "2ms", spike = { probability = 0.1, type = "constant", value = "1s" } }
+
+        [[net.links]]
+        between = ["a", "a"]
+        send_latency = "5ms"
+
+        [[net.links]]
+        between = ["a", "b"]
+        send_latency = { type = "normal", mean = "10ms", std_dev = "1ms" }
+
+        [[net.links]]
+        between = ["a", "c"]
+        send_latency = { type = "pareto", scale = "1ms", shape = 2.0 }
+
+        [[net.links]]
+        between = ["b", "c"]
+        send_latency = { type = "histogram", buckets = [[500, 9], [2000, 1]] }
+        "#;
+        assert_eq!(validate(config), Vec::<String>::new());
+        let config = config.parse::<Config>().unwrap();
+        assert_eq!(validate(&config.to_string()), Vec::<String>::new());
+
+        // values rejected by the config are rejected by the schema
+        for invalid in [
+            "[net]\npacket_loss_rate = 1.5",
+            "[net]\nsend_latency = \"1d\"",
+            "[net]\nbandwidth = 0",
+            "scheduler = \"lifo\"",
+        ] {
+            assert!(invalid.parse::<Config>().is_err(), "{invalid}");
+            assert!(!validate(invalid).is_empty(), "{invalid}");
+        }
+        // unknown keys are ignored by the config, but caught by the schema
+        let typo = "[net]\npacket_loss = 0.1";
+        assert!(typo.parse::<Config>().is_ok());
+        assert!(!validate(typo).is_empty());
+    }
+}
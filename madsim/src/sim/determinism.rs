@@ -0,0 +1,216 @@
+//! Event log for determinism check.
+//!
+//! By default, the determinism check only records a one-byte hash of every
+//! random number, which tells when the simulation diverges but not why.
+//! The full check records every event in the simulation, including task polls,
+//! timer events, network packets and the call site of random numbers.
+//! On divergence, the differing events are shown along with the last common ones.
+
+use crate::{net::IpProtocol, task::NodeId};
+use std::{
+    fmt::{self, Write},
+    net::SocketAddr,
+    panic::Location,
+    time::Duration,
+};
+
+/// The number of common events shown before the diverging one.
+const CONTEXT: usize = 20;
+
+/// An event in the simulation.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// A random number is generated.
+    Rand {
+        location: &'static Location<'static>,
+        hash: u8,
+    },
+    /// A task is polled.
+    Poll {
+        node: NodeId,
+        node_name: String,
+        task: crate::task::Id,
+        name: Option<String>,
+    },
+    /// Time advanced to the next timer event.
+    Timer,
+    /// A packet is sent to the network.
+    Send {
+        node: NodeId,
+        dst: SocketAddr,
+        protocol: IpProtocol,
+    },
+    /// A packet is delivered to the destination.
+    Deliver {
+        node: NodeId,
+        dst: SocketAddr,
+        protocol: IpProtocol,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Rand { location, .. } => write!(f, "rand at {location}"),
+            Event::Poll {
+                node,
+                node_name,
+                task,
+                name,
+            } => {
+                write!(f, "poll task {task}")?;
+                if let Some(name) = name {
+                    write!(f, " {name:?}")?;
+                }
+                write!(f, " on {node} {node_name:?}")
+            }
+            Event::Timer => write!(f, "timer"),
+            Event::Send {
+                node,
+                dst,
+                protocol,
+            } => write!(f, "send {protocol:?} from {node} to {dst}"),
+            Event::Deliver {
+                node,
+                dst,
+                protocol,
+            } => write!(f, "deliver {protocol:?} from {node} to {dst}"),
+        }
+    }
+}
+
+/// An event with the time it happened.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub time: Option<Duration>,
+    pub event: Event,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time {
+            Some(time) => write!(f, "[{time:?}] {}", self.event),
+            None => write!(f, "[-] {}", self.event),
+        }
+    }
+}
+
+/// Generate a report of divergence at `pos`.
+///
+/// `actual` is `None` if the second run ended before the first one.
+pub(crate) fn report(expected: &[Entry], pos: usize, actual: Option<&Entry>) -> String {
+    let mut s = String::new();
+    let time = (actual.or_else(|| expected.get(pos))).and_then(|e| e.time);
+    match time {
+        Some(time) => writeln!(s, "non-determinism detected at event #{pos} ({time:?})"),
+        None => writeln!(s, "non-determinism detected at event #{pos}"),
+    }
+    .unwrap();
+    let start = pos.saturating_sub(CONTEXT);
+    if start < pos {
+        writeln!(s, "last {} common events:", pos - start).unwrap();
+        for (i, entry) in expected[start..pos].iter().enumerate() {
+            writeln!(s, "  #{:<8} {entry}", start + i).unwrap();
+        }
+    }
+    let first = expected
+        .get(pos)
+        .map_or("<end of run>".into(), |e| e.to_string());
+    let second = actual.map_or("<end of run>".into(), |e| e.to_string());
+    let width = first.len().max("first run".len());
+    writeln!(s, "diverging event #{pos}:").unwrap();
+    writeln!(s, "  {:width$} | second run", "first run").unwrap();
+    write!(s, "  {first:width$} | {second}").unwrap();
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        net::Endpoint,
+        rand::{thread_rng, Rng},
+        runtime::Runtime,
+        task::spawn,
+        time::{sleep, Duration},
+        Config,
+    };
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    #[test]
+    fn deterministic() {
+        let ret = Runtime::check_determinism_full(1, Config::default(), || async {
+            let handle = crate::runtime::Handle::current();
+            let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+            let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+            let node1 = handle.create_node().ip(addr1.ip()).build();
+            let node2 = handle.create_node().ip(addr2.ip()).build();
+            node1.spawn(async move {
+                let net = Endpoint::bind(addr1).await.unwrap();
+                // wait for the receiver to bind
+                sleep(Duration::from_secs(1)).await;
+                for i in 0..10 {
+                    net.send_to(addr2, 1, &[i]).await.unwrap();
+                    sleep(Duration::from_millis(thread_rng().gen_range(1..10))).await;
+                }
+            });
+            let f = node2.spawn(async move {
+                let net = Endpoint::bind(addr2).await.unwrap();
+                let mut buf = [0; 1];
+                let mut sum = 0;
+                for _ in 0..10 {
+                    net.recv_from(1, &mut buf).await.unwrap();
+                    sum += buf[0];
+                }
+                sum
+            });
+            f.await.unwrap()
+        });
+        assert_eq!(ret, 45);
+    }
+
+    #[test]
+    fn report_divergence() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let err = std::panic::catch_unwind(|| {
+            Runtime::check_determinism_full(1, Config::default(), || async {
+                spawn(async { sleep(Duration::from_millis(1)).await })
+                    .await
+                    .unwrap();
+                // the second run generates one more random number
+                if RUNS.fetch_add(1, Ordering::Relaxed) == 1 {
+                    crate::rand::random::<u64>();
+                }
+                sleep(Duration::from_millis(1)).await;
+            })
+        })
+        .unwrap_err();
+        let msg = err.downcast::<String>().unwrap();
+        assert!(msg.starts_with("non-determinism detected at event #"));
+        assert!(msg.contains("common events:"));
+        assert!(msg.contains("first run"));
+        assert!(msg.contains(&format!("] rand at {}:", file!())));
+    }
+
+    #[test]
+    fn report_thread_rng_location() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let err = std::panic::catch_unwind(|| {
+            Runtime::check_determinism_full(1, Config::default(), || async {
+                let mut rng = thread_rng();
+                rng.gen_range(0..10);
+                if RUNS.fetch_add(1, Ordering::Relaxed) == 1 {
+                    rng.gen_range(0..10);
+                }
+                sleep(Duration::from_millis(1)).await;
+            })
+        })
+        .unwrap_err();
+        let msg = err.downcast::<String>().unwrap();
+        // the location is where `thread_rng` is called, not inside the rand crate
+        assert!(msg.contains(&format!("] rand at {}:", file!())), "{msg}");
+        assert!(!msg.contains("rand-0."), "{msg}");
+    }
+}
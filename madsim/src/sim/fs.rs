@@ -10,7 +10,7 @@ use std::{
 };
 
 use crate::{
-    plugin::{node, simulator, Simulator},
+    plugin::{node, simulator, ResetKind, Simulator},
     rand::GlobalRng,
     task::NodeId,
     time::TimeHandle,
@@ -34,8 +34,17 @@ impl Simulator for FsSim {
         handles.insert(id, FsNodeHandle::new(id));
     }
 
-    fn reset_node(&self, id: NodeId) {
-        self.power_fail(id);
+    fn on_kill(&self, id: NodeId, kind: ResetKind) {
+        match kind {
+            // the page cache survives a process crash
+            ResetKind::Crash => {}
+            ResetKind::PowerLoss => self.power_fail(id),
+        }
+    }
+
+    fn on_remove_node(&self, id: NodeId) {
+        let mut handles = self.handles.lock();
+        handles.remove(&id);
     }
 }
 
@@ -47,8 +56,14 @@ impl FsSim {
     }
 
     /// Simulate a power failure. All data that does not reach the disk will be lost.
-    pub fn power_fail(&self, _id: NodeId) {
-        // TODO
+    ///
+    /// Files are restored to the content at their last [`File::sync_all`].
+    /// Files that have never been synced are removed.
+    pub fn power_fail(&self, id: NodeId) {
+        trace!("fs: power fail at {}", id);
+        let handle = self.get_node(id);
+        let mut fs = handle.fs.lock();
+        fs.retain(|_, inode| inode.power_fail());
     }
 
     /// Get the size of given file.
@@ -124,7 +139,10 @@ impl FsNodeHandle {
 
 struct INode {
     path: PathBuf,
+    /// The data in the page cache.
     data: RwLock<Vec<u8>>,
+    /// The data on the disk, or `None` if the file has never been synced.
+    synced: RwLock<Option<Vec<u8>>>,
 }
 
 impl INode {
@@ -132,6 +150,24 @@ impl INode {
         INode {
             path: path.into(),
             data: RwLock::new(Vec::new()),
+            synced: RwLock::new(None),
+        }
+    }
+
+    fn sync(&self) {
+        *self.synced.write() = Some(self.data.read().clone());
+    }
+
+    /// Drop the data not synced to the disk.
+    ///
+    /// Returns `false` if the file has never been synced.
+    fn power_fail(&self) -> bool {
+        match &*self.synced.read() {
+            Some(synced) => {
+                *self.data.write() = synced.clone();
+                true
+            }
+            None => false,
         }
     }
 
@@ -221,6 +257,7 @@ impl File {
     /// Attempts to sync all OS-internal metadata to disk.
     pub async fn sync_all(&self) -> Result<()> {
         trace!("file({:?}): sync_all", self.inode.path);
+        self.inode.sync();
         // TODO: random delay
         Ok(())
     }
@@ -296,4 +333,70 @@ mod tests {
         });
         runtime.block_on(f).unwrap();
     }
+
+    #[test]
+    fn replace_node() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().name("server").build();
+        let f = node.spawn(async move {
+            let file = File::create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+        });
+        runtime.block_on(f).unwrap();
+
+        // the new machine has an empty disk
+        let node = runtime.handle().replace_node(node.id()).build();
+        let f = node.spawn(async move {
+            assert_eq!(
+                File::open("file").await.err().unwrap().kind(),
+                ErrorKind::NotFound
+            );
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn crash_and_power_loss() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let f = node.spawn(async move {
+            let file = File::create("synced").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+            file.write_all_at(b" world", 5).await.unwrap();
+            File::create("unsynced").await.unwrap();
+        });
+        runtime.block_on(f).unwrap();
+
+        // data in the page cache survives a process crash
+        runtime.handle().restart_process(node.id());
+        let node = runtime.handle().get_node(node.id()).unwrap();
+        let f = node.spawn(async move {
+            assert_eq!(read("synced").await.unwrap(), b"hello world");
+            assert!(metadata("unsynced").await.is_ok());
+        });
+        runtime.block_on(f).unwrap();
+
+        // so does it when the node is killed or restarted
+        runtime.handle().kill(node.id());
+        runtime.handle().restart(node.id());
+        let node = runtime.handle().get_node(node.id()).unwrap();
+        let f = node.spawn(async move {
+            assert_eq!(read("synced").await.unwrap(), b"hello world");
+            assert!(metadata("unsynced").await.is_ok());
+        });
+        runtime.block_on(f).unwrap();
+
+        // unsynced data is lost on power loss
+        runtime.handle().reboot(node.id());
+        let node = runtime.handle().get_node(node.id()).unwrap();
+        let f = node.spawn(async move {
+            assert_eq!(read("synced").await.unwrap(), b"hello");
+            assert_eq!(
+                metadata("unsynced").await.err().unwrap().kind(),
+                ErrorKind::NotFound
+            );
+        });
+        runtime.block_on(f).unwrap();
+    }
 }
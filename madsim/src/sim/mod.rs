@@ -1,6 +1,6 @@
 #![deny(missing_docs)]
 
-pub use self::config::Config;
+pub use self::config::{Config, ConfigError, SimulatorConfigs};
 pub(crate) use self::runtime::context;
 
 #[cfg(feature = "macros")]
@@ -8,13 +8,18 @@ pub(crate) use self::runtime::context;
 pub use madsim_macros::{main, test, tokio_main, tokio_test};
 
 mod config;
+pub(crate) mod determinism;
 pub mod fs;
 pub mod net;
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
 pub mod plugin;
 pub mod rand;
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub mod replay;
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
 pub mod runtime;
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub mod strict;
 pub mod task;
 pub mod time;
 mod utils;
@@ -28,7 +28,7 @@ use std::future;
 use std::io;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 
-/// Performs a DNS resolution.
+/// Performs a DNS resolution by the simulated [`Dns`](super::Dns).
 pub async fn lookup_host(host: impl ToSocketAddrs) -> io::Result<impl Iterator<Item = SocketAddr>> {
     to_socket_addrs(host).await
 }
@@ -37,7 +37,8 @@ pub async fn lookup_host(host: impl ToSocketAddrs) -> io::Result<impl Iterator<I
 ///
 /// # DNS
 ///
-/// Implementations of `ToSocketAddrs` for string types require a DNS lookup.
+/// Implementations of `ToSocketAddrs` for string types require a DNS lookup,
+/// which is resolved by the simulated [`Dns`](super::Dns).
 ///
 /// # Calling
 ///
@@ -194,7 +195,6 @@ impl sealed::ToSocketAddrsPriv for str {
     type Future = sealed::MaybeReady;
 
     fn to_socket_addrs(&self, _: sealed::Internal) -> Self::Future {
-        use crate::task::spawn_blocking;
         use sealed::MaybeReady;
 
         // First check if the input parses as a socket address
@@ -204,12 +204,17 @@ impl sealed::ToSocketAddrsPriv for str {
             return MaybeReady(sealed::State::Ready(Some(addr)));
         }
 
-        // Run DNS lookup on the blocking pool
-        let s = self.to_owned();
-
-        MaybeReady(sealed::State::Blocking(spawn_blocking(move || {
-            std::net::ToSocketAddrs::to_socket_addrs(&s)
-        })))
+        // Split the host and port, then resolve the host by the simulated DNS
+        match self
+            .rsplit_once(':')
+            .map(|(host, port)| (host, port.parse()))
+        {
+            Some((host, Ok(port))) => (host, port).to_socket_addrs(sealed::Internal),
+            _ => MaybeReady(sealed::State::Error(Some(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid socket address",
+            )))),
+        }
     }
 }
 
@@ -222,7 +227,6 @@ impl sealed::ToSocketAddrsPriv for (&str, u16) {
     type Future = sealed::MaybeReady;
 
     fn to_socket_addrs(&self, _: sealed::Internal) -> Self::Future {
-        use crate::task::spawn_blocking;
         use sealed::MaybeReady;
 
         let (host, port) = *self;
@@ -244,8 +248,12 @@ impl sealed::ToSocketAddrsPriv for (&str, u16) {
 
         let host = host.to_owned();
 
-        MaybeReady(sealed::State::Blocking(spawn_blocking(move || {
-            std::net::ToSocketAddrs::to_socket_addrs(&(&host[..], port))
+        // Run DNS lookup in the simulation
+        MaybeReady(sealed::State::Blocking(crate::task::spawn(async move {
+            let net = crate::plugin::simulator::<super::NetSim>();
+            let ips = net.lookup_host(&host).await?;
+            let addrs = ips.into_iter().map(|ip| SocketAddr::new(ip, port));
+            Ok(addrs.collect::<Vec<_>>().into_iter())
         })))
     }
 }
@@ -311,6 +319,7 @@ pub(crate) mod sealed {
     pub(super) enum State {
         Ready(Option<SocketAddr>),
         Blocking(JoinHandle<io::Result<vec::IntoIter<SocketAddr>>>),
+        Error(Option<io::Error>),
     }
 
     #[doc(hidden)]
@@ -337,6 +346,9 @@ pub(crate) mod sealed {
 
                     Poll::Ready(res)
                 }
+                State::Error(ref mut e) => {
+                    Poll::Ready(Err(e.take().expect("polled after completion")))
+                }
             }
         }
     }
@@ -0,0 +1,342 @@
+//! Simulated DNS.
+
+use super::NetSim;
+use crate::task::NodeId;
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+/// The time to wait for a response before retrying, as the default of glibc.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of queries before giving up, as the default of glibc.
+pub(crate) const ATTEMPTS: u32 = 2;
+
+/// The size of a query or response in bytes.
+pub(crate) const PACKET_SIZE: u64 = 512;
+
+/// The maximum length of an alias chain.
+const MAX_ALIASES: usize = 8;
+
+/// A failure injected to the resolution of a name.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DnsFailure {
+    /// The name does not exist.
+    NxDomain,
+    /// The server does not respond.
+    Timeout,
+}
+
+/// DNS records of the network.
+#[derive(Default)]
+pub(crate) struct DnsTable {
+    /// Static A and AAAA records.
+    records: HashMap<String, Vec<IpAddr>>,
+    /// Nodes registered by their hostnames, which resolve to the current IP of the node.
+    hosts: HashMap<String, Vec<NodeId>>,
+    /// CNAME records.
+    aliases: HashMap<String, String>,
+    failures: HashMap<String, DnsFailure>,
+    /// The node running the DNS server.
+    server: Option<NodeId>,
+}
+
+impl DnsTable {
+    /// Register a hostname of the node.
+    pub fn add_host(&mut self, name: &str, node: NodeId) {
+        let nodes = self.hosts.entry(normalize(name)).or_default();
+        if !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+
+    /// Returns all hostnames of the node.
+    pub fn hostnames(&self, node: NodeId) -> Vec<String> {
+        let mut names = (self.hosts.iter())
+            .filter(|(_, nodes)| nodes.contains(&node))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Unregister all hostnames of the node.
+    pub fn remove_node(&mut self, node: NodeId) {
+        for nodes in self.hosts.values_mut() {
+            nodes.retain(|&n| n != node);
+        }
+        self.hosts.retain(|_, nodes| !nodes.is_empty());
+        if self.server == Some(node) {
+            self.server = None;
+        }
+    }
+
+    pub fn server(&self) -> Option<NodeId> {
+        self.server
+    }
+
+    /// Returns the failure injected to the name or any name in its alias chain.
+    pub fn failure(&self, name: &str) -> Option<DnsFailure> {
+        (self.chain(name).iter()).find_map(|name| self.failures.get(name).copied())
+    }
+
+    /// Returns the name followed by the targets of its aliases.
+    fn chain(&self, name: &str) -> Vec<String> {
+        let mut chain = vec![normalize(name)];
+        for _ in 0..MAX_ALIASES {
+            match self.aliases.get(chain.last().unwrap()) {
+                Some(next) if !chain.contains(next) => chain.push(next.clone()),
+                _ => break,
+            }
+        }
+        chain
+    }
+}
+
+/// A handle to the simulated DNS of [`NetSim`].
+///
+/// A node is registered by its name when it is built, and additional names can
+/// be set by [`NodeBuilder::hostname`](crate::runtime::NodeBuilder::hostname).
+/// These names always resolve to the current IP address of the node.
+///
+/// Names are resolved by [`lookup_host`](super::lookup_host) and all functions
+/// accepting [`ToSocketAddrs`](super::ToSocketAddrs). A query travels between
+/// the node and the DNS server, so it is subject to the latency, packet loss
+/// and partitions of the network. A lost query is retried after 5 seconds,
+/// and the lookup fails with [`TimedOut`](io::ErrorKind::TimedOut) after 2 attempts.
+///
+/// # Example
+///
+/// ```
+/// use madsim::{net::{self, DnsFailure, NetSim}, plugin, runtime::Runtime};
+/// use std::io::ErrorKind;
+///
+/// let rt = Runtime::new();
+/// let node = rt.create_node().name("client").ip([10, 0, 0, 1].into()).build();
+/// rt.create_node().name("db-1").ip([10, 0, 0, 2].into()).build();
+///
+/// let f = node.spawn(async move {
+///     let net = plugin::simulator::<NetSim>();
+///     let dns = net.dns();
+///     dns.alias("db.local", "db-1");
+///     dns.add("cache.local", [10, 0, 0, 9].into());
+///     dns.inject_failure("flaky.local", DnsFailure::NxDomain);
+///
+///     let addrs: Vec<_> = net::lookup_host("db.local:5432").await.unwrap().collect();
+///     assert_eq!(addrs, ["10.0.0.2:5432".parse().unwrap()]);
+///     let mut addrs = net::lookup_host(("cache.local", 80)).await.unwrap();
+///     assert_eq!(addrs.next(), Some("10.0.0.9:80".parse().unwrap()));
+///     let err = net::lookup_host("flaky.local:80").await.err().unwrap();
+///     assert_eq!(err.kind(), ErrorKind::NotFound);
+/// });
+/// rt.block_on(f).unwrap();
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub struct Dns<'a> {
+    pub(super) net: &'a NetSim,
+}
+
+impl Dns<'_> {
+    /// Add an A or AAAA record.
+    pub fn add(&self, name: &str, ip: IpAddr) {
+        let mut table = self.net.dns.lock();
+        let ips = table.records.entry(normalize(name)).or_default();
+        if !ips.contains(&ip) {
+            ips.push(ip);
+        }
+    }
+
+    /// Remove all records of the name, including the hostnames of nodes and alias.
+    pub fn remove(&self, name: &str) {
+        let name = normalize(name);
+        let mut table = self.net.dns.lock();
+        table.records.remove(&name);
+        table.hosts.remove(&name);
+        table.aliases.remove(&name);
+    }
+
+    /// Add a CNAME record, so that `alias` resolves to the addresses of `target`.
+    ///
+    /// An alias overrides other records of the same name.
+    pub fn alias(&self, alias: &str, target: &str) {
+        let mut table = self.net.dns.lock();
+        table.aliases.insert(normalize(alias), normalize(target));
+    }
+
+    /// Make the resolution of the name, and of all aliases to it, fail until
+    /// [`clear_failure`](Dns::clear_failure).
+    pub fn inject_failure(&self, name: &str, failure: DnsFailure) {
+        let mut table = self.net.dns.lock();
+        table.failures.insert(normalize(name), failure);
+    }
+
+    /// Clear the failure injected to the name.
+    pub fn clear_failure(&self, name: &str) {
+        let mut table = self.net.dns.lock();
+        table.failures.remove(&normalize(name));
+    }
+
+    /// Set the node running the DNS server.
+    ///
+    /// Queries from a node are lost if the link to the server is clogged.
+    /// By default, the server is always reachable unless the node is disconnected.
+    pub fn set_server(&self, node: Option<NodeId>) {
+        let mut table = self.net.dns.lock();
+        table.server = node;
+    }
+
+    /// Returns the addresses of the name without simulating a query.
+    ///
+    /// Returns an error of [`NotFound`](io::ErrorKind::NotFound) if the name
+    /// doesn't exist. Injected failures are ignored.
+    pub fn lookup(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        if normalize(name) == "localhost" {
+            return Ok(vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()]);
+        }
+        let table = self.net.dns.lock();
+        let target = table.chain(name).pop().unwrap();
+        let mut ips = table.records.get(&target).cloned().unwrap_or_default();
+        for &node in table.hosts.get(&target).into_iter().flatten() {
+            if let Some(ip) = self.net.get_ip(node) {
+                if !ips.contains(&ip) {
+                    ips.push(ip);
+                }
+            }
+        }
+        if ips.is_empty() {
+            return Err(not_found(name));
+        }
+        Ok(ips)
+    }
+}
+
+/// Returns the error of a name that doesn't exist.
+pub(crate) fn not_found(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("failed to lookup address information: {name}: Name or service not known"),
+    )
+}
+
+/// Names are case-insensitive and may end with a dot.
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::lookup_host,
+        plugin::simulator,
+        runtime::{Handle, Runtime},
+        time::Instant,
+    };
+    use std::net::SocketAddr;
+
+    async fn lookup(host: &str) -> io::Result<Vec<SocketAddr>> {
+        Ok(lookup_host(host).await?.collect())
+    }
+
+    #[test]
+    fn resolve() {
+        let runtime = Runtime::new();
+        let node1 = runtime
+            .create_node()
+            .name("server")
+            .hostname("API.local")
+            .ip([10, 0, 0, 1].into())
+            .build();
+        let node2 = runtime.create_node().ip([10, 0, 0, 2].into()).build();
+        let id1 = node1.id();
+        let f = node2.spawn(async move {
+            let net = simulator::<NetSim>();
+            let addr = "10.0.0.1:80".parse::<SocketAddr>().unwrap();
+
+            // the query takes the round trip time
+            let t0 = Instant::now();
+            assert_eq!(lookup("server:80").await.unwrap(), [addr]);
+            assert!(t0.elapsed() >= Duration::from_millis(2));
+            assert_eq!(lookup("api.LOCAL.:80").await.unwrap(), [addr]);
+            assert_eq!(
+                lookup("localhost:80").await.unwrap()[0],
+                "127.0.0.1:80".parse::<SocketAddr>().unwrap()
+            );
+            let err = lookup("unknown:80").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            let err = lookup("server").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+            // hostnames follow the IP address of the node
+            net.set_ip(id1, [10, 0, 0, 3].into());
+            assert_eq!(
+                lookup("server:80").await.unwrap(),
+                ["10.0.0.3:80".parse().unwrap()]
+            );
+
+            // static records and aliases
+            net.dns().add("server", [10, 0, 0, 9].into());
+            net.dns().alias("www", "server");
+            assert_eq!(
+                net.dns().lookup("www").unwrap(),
+                [IpAddr::from([10, 0, 0, 9]), IpAddr::from([10, 0, 0, 3])]
+            );
+            net.dns().remove("server");
+            assert!(net.dns().lookup("www").is_err());
+
+            // hostnames are unregistered when the node is removed
+            Handle::current().remove_node(id1);
+            assert!(net.dns().lookup("api.local").is_err());
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn failures() {
+        let runtime = Runtime::new();
+        let server = runtime.create_node().ip([10, 0, 0, 1].into()).build();
+        let node = runtime.create_node().ip([10, 0, 0, 2].into()).build();
+        let (server, id) = (server.id(), node.id());
+        let f = node.spawn(async move {
+            let net = simulator::<NetSim>();
+            net.dns().add("db", [10, 0, 0, 3].into());
+
+            net.dns().inject_failure("db", DnsFailure::NxDomain);
+            let err = lookup("db:80").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+            net.dns().inject_failure("db", DnsFailure::Timeout);
+            let t0 = Instant::now();
+            let err = lookup("db:80").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert!(t0.elapsed() >= TIMEOUT * ATTEMPTS);
+
+            net.dns().clear_failure("db");
+            assert!(lookup("db:80").await.is_ok());
+
+            // failures of the target apply to its aliases
+            net.dns().alias("www", "web");
+            net.dns().alias("web", "db");
+            net.dns().inject_failure("web", DnsFailure::NxDomain);
+            let err = lookup("www:80").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert!(lookup("db:80").await.is_ok());
+            net.dns().clear_failure("web");
+            assert!(lookup("www:80").await.is_ok());
+
+            // partitioned from the DNS server
+            net.dns().set_server(Some(server));
+            net.disconnect2(id, server);
+            let err = lookup("db:80").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            net.connect2(id, server);
+            assert!(lookup("db:80").await.is_ok());
+        });
+        runtime.block_on(f).unwrap();
+    }
+}
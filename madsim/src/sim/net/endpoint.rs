@@ -107,6 +107,18 @@ impl Endpoint {
     /// It is provided for use by other simulators.
     #[cfg_attr(docsrs, doc(cfg(madsim)))]
     pub async fn send_to_raw(&self, dst: SocketAddr, tag: u64, data: Payload) -> io::Result<()> {
+        let size = payload_size(&data);
+        self.send_to_raw_with_size(dst, tag, data, size).await
+    }
+
+    /// Sends a raw message of `size` bytes.
+    pub(crate) async fn send_to_raw_with_size(
+        &self,
+        dst: SocketAddr,
+        tag: u64,
+        data: Payload,
+        size: u64,
+    ) -> io::Result<()> {
         trace!("send: {} -> {dst}, tag={tag}", self.guard.addr);
         self.guard
             .net
@@ -116,6 +128,7 @@ impl Endpoint {
                 dst,
                 Udp,
                 Box::new((tag, data)),
+                size,
             )
             .await?;
         Ok(())
@@ -528,4 +541,242 @@ mod tests {
 
         runtime.block_on(f).unwrap();
     }
+
+    #[test]
+    fn topology() {
+        let ms = Duration::from_millis;
+        let range = |t: Duration| Latency::from(t..t + Duration::from_nanos(1));
+        let config: crate::Config = r#"
+        [net]
+        send_latency = "1ms..1.000001ms"
+
+        [[net.links]]
+        between = ["a", "b"]
+        packet_loss_rate = 0.5
+        send_latency = "80ms..80.000001ms"
+
+        [[net.links]]
+        between = ["b1", "a1"]
+        send_latency = "50ms"
+        "#
+        .parse()
+        .unwrap();
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node = |ip: SocketAddr, zone: &str, region: &str| {
+            let node = (runtime.create_node().ip(ip.ip()))
+                .zone(zone)
+                .region(region);
+            node.build()
+        };
+        let node1 = node(addr1, "a1", "a");
+        let node2 = node(addr2, "b2", "b");
+        let node3 = node("10.0.0.3:1".parse().unwrap(), "b1", "b");
+        let node4 = node("10.0.0.4:1".parse().unwrap(), "a2", "a");
+        assert_eq!(node1.zone().as_deref(), Some("a1"));
+        assert_eq!(node1.region().as_deref(), Some("a"));
+        let (id1, id2, id3, id4) = (node1.id(), node2.id(), node3.id(), node4.id());
+
+        let f = node2.spawn(async move {
+            let net = simulator::<NetSim>();
+            // matched by regions
+            let link = net.link_config(id1, id2);
+            assert_eq!(
+                (link.packet_loss_rate, link.send_latency.clone()),
+                (0.5, range(ms(80)))
+            );
+            assert_eq!(net.link_config(id2, id1), link);
+            // matched by zones
+            assert_eq!(
+                net.link_config(id3, id1).send_latency,
+                Distribution::Constant { value: ms(50) }.into()
+            );
+            // no rule matches
+            assert_eq!(net.link_config(id1, id4).send_latency, range(ms(1)));
+
+            let config = LinkConfig {
+                packet_loss_rate: 0.0,
+                send_latency: (ms(100)..ms(101)).into(),
+                bandwidth: None,
+            };
+            net.set_link_config(id1, id2, config.clone());
+            assert_eq!(net.link_config(id2, id1), config);
+
+            // the latency applies to messages
+            let ep = Endpoint::bind(addr2).await.unwrap();
+            let t0 = Instant::now();
+            let mut buf = vec![0; 0x10];
+            ep.recv_from(1, &mut buf).await.unwrap();
+            assert!(t0.elapsed() >= ms(100));
+
+            net.reset_link_config(id1, id2);
+            assert_eq!(net.link_config(id1, id2).send_latency, range(ms(80)));
+
+            // rules changed at runtime take effect
+            net.update_config(|config| config.links.clear());
+            assert_eq!(net.link_config(id1, id2).send_latency, range(ms(1)));
+        });
+        node1.spawn(async move {
+            let ep = Endpoint::bind(addr1).await.unwrap();
+            ep.send_to(addr2, 1, b"ping").await.unwrap();
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn bandwidth() {
+        let ms = Duration::from_millis;
+        let mut config = crate::Config::default();
+        config.net.send_latency = Distribution::Constant { value: ms(1) }.into();
+        config.net.nic_bandwidth = Some(1_000_000);
+        config.net.queue_capacity = Some(1_500_000);
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let id1 = node1.id();
+        let barrier = Arc::new(Barrier::new(2));
+
+        let barrier_ = barrier.clone();
+        node1.spawn(async move {
+            let net = simulator::<NetSim>();
+            let ep = Endpoint::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            // 1 MB takes 1s to transmit, then the heartbeat waits behind it
+            ep.send_to(addr2, 1, &vec![0; 1_000_000]).await.unwrap();
+            assert!(net.nic_queue_bytes(id1) > 990_000);
+            ep.send_to(addr2, 2, b"heartbeat").await.unwrap();
+            // the queue overflows
+            ep.send_to(addr2, 3, &vec![0; 1_000_000]).await.unwrap();
+            assert_eq!(net.stat().overflow_count, 1);
+            assert!(net.stat().max_queue_bytes > 990_000);
+        });
+
+        let f = node2.spawn(async move {
+            let ep = Endpoint::bind(addr2).await.unwrap();
+            barrier.wait().await;
+            let t0 = Instant::now();
+            let mut buf = vec![0; 1_000_000];
+            ep.recv_from(1, &mut buf).await.unwrap();
+            let t1 = t0.elapsed();
+            assert!(t1 >= ms(1001) && t1 < ms(1002), "{t1:?}");
+            ep.recv_from(2, &mut buf).await.unwrap();
+            assert!(t0.elapsed() - t1 < ms(1));
+            let res = crate::time::timeout(ms(5000), ep.recv_from(3, &mut buf)).await;
+            assert!(res.is_err());
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn overflow_in_link_queue() {
+        let mut config = crate::Config::default();
+        config.net.nic_bandwidth = Some(1_000_000);
+        config.net.queue_capacity = Some(1_500_000);
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let (id1, id2) = (node1.id(), node2.id());
+
+        let f = node1.spawn(async move {
+            let net = simulator::<NetSim>();
+            let link = LinkConfig {
+                bandwidth: Some(100_000),
+                ..net.link_config(id1, id2)
+            };
+            net.set_link_config(id1, id2, link);
+            let ep = Endpoint::bind(addr1).await.unwrap();
+            ep.send_to(addr2, 1, &vec![0; 600_000]).await.unwrap();
+            crate::time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(net.nic_queue_bytes(id1), 0);
+            // fits in the NIC queue but overflows the link queue
+            ep.send_to(addr2, 1, &vec![0; 1_200_000]).await.unwrap();
+            assert_eq!(net.stat().overflow_count, 1);
+            assert_eq!(net.nic_queue_bytes(id1), 0);
+        });
+        node2.spawn(async move {
+            let _ep = Endpoint::bind(addr2).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        runtime.block_on(f).unwrap();
+    }
+
+    #[test]
+    fn invalid_link_config() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let runtime = Runtime::new();
+        let id1 = runtime.create_node().build().id();
+        let id2 = runtime.create_node().build().id();
+        runtime.block_on(async move {
+            let net = simulator::<NetSim>();
+            let mut link = net.link_config(id1, id2);
+            link.send_latency.spike = Some(Spike {
+                probability: 2.0,
+                distribution: Distribution::Constant { value: Duration::ZERO },
+            });
+            let res = catch_unwind(AssertUnwindSafe(|| net.set_link_config(id1, id2, link)));
+            let err = res.unwrap_err();
+            assert_eq!(
+                crate::task::panic_message(&*err),
+                Some("invalid value for `link.send_latency.spike.probability`: 2 is not a possibility in [0, 1]")
+            );
+            let res = catch_unwind(AssertUnwindSafe(|| {
+                net.update_config(|config| config.bandwidth = Some(0));
+            }));
+            assert!(res.is_err());
+            // the config is unchanged
+            assert_eq!(net.link_config(id1, id2).bandwidth, None);
+        });
+    }
+
+    #[test]
+    fn rpc_data_size() {
+        use crate::net::rpc::{Deserialize, Request, Serialize};
+
+        #[derive(Serialize, Deserialize)]
+        struct Upload;
+        impl Request for Upload {
+            const ID: u64 = 1;
+            type Response = ();
+        }
+
+        let mut config = crate::Config::default();
+        config.net.send_latency = Distribution::Constant {
+            value: Duration::ZERO,
+        }
+        .into();
+        config.net.nic_bandwidth = Some(1_000_000);
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+
+        let barrier_ = barrier.clone();
+        node2.spawn(async move {
+            let ep = Arc::new(Endpoint::bind(addr2).await.unwrap());
+            ep.add_rpc_handler_with_data(|Upload, _data| async move { ((), vec![0; 500_000]) });
+            barrier_.wait().await;
+            std::future::pending::<()>().await;
+        });
+        let f = node1.spawn(async move {
+            let ep = Endpoint::bind(addr1).await.unwrap();
+            barrier.wait().await;
+            let t0 = Instant::now();
+            let (_, data) = ep
+                .call_with_data(addr2, Upload, &[0; 1_000_000])
+                .await
+                .unwrap();
+            assert_eq!(data.len(), 500_000);
+            // the request and response take 1.5s to transmit
+            assert!(t0.elapsed() >= Duration::from_millis(1500));
+        });
+        runtime.block_on(f).unwrap();
+    }
 }
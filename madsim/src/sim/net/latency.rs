@@ -0,0 +1,545 @@
+//! Latency distributions.
+
+use crate::sim::config::{
+    duration,
+    error::{check_probability, ConfigError},
+};
+use rand::Rng;
+use serde::{
+    de::{self, value::MapAccessDeserializer, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Range,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// The maximum latency of a sample.
+const MAX_LATENCY: Duration = Duration::from_secs(3600);
+
+/// The latency of sending packets.
+///
+/// It is a [`Distribution`] with an optional [`Spike`]. In the TOML config,
+/// a range like `"1ms..10ms"` is a uniform distribution, and a duration like
+/// `"5ms"` is a constant. Other distributions are selected by `type`:
+///
+/// ```toml
+/// [net.send_latency]
+/// type = "log_normal"
+/// median = "2ms"
+/// sigma = 0.5
+/// spike = { probability = 0.001, type = "constant", value = "1s" }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, PartialEq, Clone, Hash)]
+pub struct Latency {
+    /// The distribution of latency.
+    pub distribution: Distribution,
+    /// A rare extra delay.
+    pub spike: Option<Spike>,
+}
+
+/// The table form of [`Latency`].
+#[derive(Serialize, Deserialize)]
+struct LatencyTable<D> {
+    #[serde(flatten)]
+    distribution: D,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spike: Option<Spike>,
+}
+
+impl Serialize for Latency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (&self.distribution, &self.spike) {
+            (Distribution::Constant { value }, None) => {
+                serializer.serialize_str(&duration::format(*value))
+            }
+            (Distribution::Uniform { start, end }, None) => serializer.serialize_str(&format!(
+                "{}..{}",
+                duration::format(*start),
+                duration::format(*end)
+            )),
+            (distribution, spike) => LatencyTable {
+                distribution,
+                spike: spike.clone(),
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Latency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LatencyVisitor)
+    }
+}
+
+struct LatencyVisitor;
+
+impl<'de> Visitor<'de> for LatencyVisitor {
+    type Value = Latency;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a range like \"1ms..10ms\", a duration, or a table with `type`")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if v.contains("..") {
+            duration::parse_range(v)
+                .map(Latency::from)
+                .map_err(E::custom)
+        } else {
+            let value = duration::parse(v).map_err(E::custom)?;
+            Ok(Distribution::Constant { value }.into())
+        }
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let mut table = serde_json::Map::deserialize(MapAccessDeserializer::new(map))?;
+        // a range `{ start, end }` without `type` is a uniform distribution
+        if !table.contains_key("type") && table.contains_key("start") {
+            table.insert("type".into(), "uniform".into());
+        }
+        let table = LatencyTable::<Distribution>::deserialize(serde_json::Value::Object(table))
+            .map_err(de::Error::custom)?;
+        Ok(Latency {
+            distribution: table.distribution,
+            spike: table.spike,
+        })
+    }
+}
+
+impl From<Range<Duration>> for Latency {
+    fn from(range: Range<Duration>) -> Self {
+        Distribution::Uniform {
+            start: range.start,
+            end: range.end,
+        }
+        .into()
+    }
+}
+
+impl From<Distribution> for Latency {
+    fn from(distribution: Distribution) -> Self {
+        Latency {
+            distribution,
+            spike: None,
+        }
+    }
+}
+
+impl Latency {
+    /// Draw a latency from the distribution.
+    ///
+    /// Samples are capped at 1 hour.
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let mut latency = self.distribution.sample(rng);
+        if let Some(spike) = &self.spike {
+            if rng.gen_bool(spike.probability) {
+                latency += spike.distribution.sample(rng);
+            }
+        }
+        latency.min(MAX_LATENCY)
+    }
+
+    /// Check the parameters of the distributions.
+    pub(crate) fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        self.distribution.validate(key)?;
+        if let Some(spike) = &self.spike {
+            check_probability(&format!("{key}.spike.probability"), spike.probability)?;
+            spike.distribution.validate(&format!("{key}.spike"))?;
+        }
+        Ok(())
+    }
+}
+
+/// A rare extra delay added to the latency, e.g. by a retransmission or a GC pause.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Spike {
+    /// The possibility of a spike.
+    pub probability: f64,
+    /// The distribution of the extra delay.
+    #[serde(flatten)]
+    pub distribution: Distribution,
+}
+
+#[allow(clippy::derive_hash_xor_eq)]
+impl Hash for Spike {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.probability.to_bits().hash(state);
+        self.distribution.hash(state);
+    }
+}
+
+/// A distribution of latency.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Distribution {
+    /// Always the same value.
+    Constant {
+        /// The latency.
+        #[serde(with = "duration")]
+        value: Duration,
+    },
+    /// Uniform distribution in `start..end`.
+    Uniform {
+        /// The lower bound, inclusive.
+        #[serde(with = "duration")]
+        start: Duration,
+        /// The upper bound, exclusive.
+        #[serde(with = "duration")]
+        end: Duration,
+    },
+    /// Normal distribution, truncated at zero.
+    Normal {
+        /// The mean.
+        #[serde(with = "duration")]
+        mean: Duration,
+        /// The standard deviation.
+        #[serde(with = "duration")]
+        std_dev: Duration,
+    },
+    /// Log-normal distribution.
+    LogNormal {
+        /// The median, which is `exp(μ)`.
+        #[serde(with = "duration")]
+        median: Duration,
+        /// The standard deviation of the logarithm, which is `σ`.
+        sigma: f64,
+    },
+    /// Pareto distribution, with a long tail.
+    Pareto {
+        /// The minimum value.
+        #[serde(with = "duration")]
+        scale: Duration,
+        /// The shape `α`. The smaller it is, the longer the tail is.
+        shape: f64,
+    },
+    /// Empirical distribution from a histogram.
+    Histogram(Histogram),
+}
+
+impl Distribution {
+    /// Draw a latency from the distribution.
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let secs = match self {
+            Distribution::Constant { value } => return *value,
+            Distribution::Uniform { start, end } if start >= end => return *start,
+            Distribution::Uniform { start, end } => return rng.gen_range(*start..*end),
+            Distribution::Normal { mean, std_dev } => {
+                mean.as_secs_f64() + std_dev.as_secs_f64() * standard_normal(rng)
+            }
+            Distribution::LogNormal { median, sigma } => {
+                median.as_secs_f64() * (sigma * standard_normal(rng)).exp()
+            }
+            Distribution::Pareto { scale, shape } => {
+                // inverse transform sampling with u in (0, 1]
+                let u = 1.0 - rng.gen::<f64>();
+                scale.as_secs_f64() / u.powf(1.0 / shape)
+            }
+            Distribution::Histogram(histogram) => return histogram.sample(rng),
+        };
+        if secs.is_nan() || secs <= 0.0 {
+            Duration::ZERO
+        } else if secs >= MAX_LATENCY.as_secs_f64() {
+            MAX_LATENCY
+        } else {
+            Duration::from_secs_f64(secs)
+        }
+    }
+
+    /// Check the parameters of the distribution.
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        match self {
+            Distribution::Uniform { start, end } if start > end => Err(ConfigError::invalid(
+                key,
+                format!(
+                    "the start {} is greater than the end {}",
+                    duration::format(*start),
+                    duration::format(*end)
+                ),
+            )),
+            Distribution::LogNormal { sigma, .. } if !(sigma.is_finite() && *sigma >= 0.0) => {
+                Err(ConfigError::invalid(
+                    format!("{key}.sigma"),
+                    format!("{sigma} is not a non-negative number"),
+                ))
+            }
+            Distribution::Pareto { shape, .. } if !(shape.is_finite() && *shape > 0.0) => Err(
+                ConfigError::invalid(format!("{key}.shape"), format!("{shape} is not positive")),
+            ),
+            Distribution::Histogram(histogram)
+                if histogram.buckets.iter().all(|&(_, count)| count == 0) =>
+            {
+                Err(ConfigError::invalid(
+                    format!("{key}.buckets"),
+                    "the histogram is empty",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[allow(clippy::derive_hash_xor_eq)]
+impl Hash for Distribution {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Distribution::Constant { value } => value.hash(state),
+            Distribution::Uniform { start, end } => (start, end).hash(state),
+            Distribution::Normal { mean, std_dev } => (mean, std_dev).hash(state),
+            Distribution::LogNormal { median, sigma } => (median, sigma.to_bits()).hash(state),
+            Distribution::Pareto { scale, shape } => (scale, shape.to_bits()).hash(state),
+            Distribution::Histogram(histogram) => histogram.hash(state),
+        }
+    }
+}
+
+/// Returns a sample of the standard normal distribution by the Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// An empirical distribution.
+///
+/// The buckets can be given inline as `[latency in microseconds, count]` pairs:
+///
+/// ```toml
+/// send_latency = { type = "histogram", buckets = [[500, 90], [2000, 9], [50000, 1]] }
+/// ```
+///
+/// or loaded from a file by `path` when the config is parsed. A relative path is
+/// resolved from the current directory. The file has a pair on each line:
+///
+/// ```text
+/// # latency_us count
+/// 500 90
+/// 2000 9
+/// 50000 1
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[serde(try_from = "HistogramRepr")]
+pub struct Histogram {
+    /// Pairs of latency in microseconds and the number of samples.
+    pub buckets: Vec<(u64, u64)>,
+}
+
+#[derive(Deserialize)]
+struct HistogramRepr {
+    path: Option<PathBuf>,
+    buckets: Option<Vec<(u64, u64)>>,
+}
+
+impl TryFrom<HistogramRepr> for Histogram {
+    type Error = String;
+
+    fn try_from(repr: HistogramRepr) -> Result<Self, Self::Error> {
+        let buckets = match (repr.path, repr.buckets) {
+            (Some(path), None) => {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("failed to read histogram {}: {e}", path.display()))?;
+                Histogram::parse(&content)
+                    .map_err(|e| format!("invalid histogram {}: {e}", path.display()))?
+            }
+            (None, Some(buckets)) => buckets,
+            _ => return Err("histogram should have either `path` or `buckets`".into()),
+        };
+        if buckets.iter().all(|&(_, count)| count == 0) {
+            return Err("histogram should not be empty".into());
+        }
+        Ok(Histogram { buckets })
+    }
+}
+
+impl Histogram {
+    /// Parse lines of latency in microseconds and count.
+    fn parse(content: &str) -> Result<Vec<(u64, u64)>, String> {
+        let mut buckets = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split(|c: char| c.is_whitespace() || c == ',');
+            let mut next = || fields.find(|s| !s.is_empty())?.parse::<u64>().ok();
+            match (next(), next()) {
+                (Some(latency), Some(count)) => buckets.push((latency, count)),
+                _ => return Err(format!("expect `<latency_us> <count>` at line {}", i + 1)),
+            }
+        }
+        Ok(buckets)
+    }
+
+    /// Draw a latency from the histogram.
+    ///
+    /// Returns zero if the histogram is empty.
+    fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let total = self.buckets.iter().map(|&(_, count)| count).sum::<u64>();
+        if total == 0 {
+            return Duration::ZERO;
+        }
+        let mut n = rng.gen_range(0..total);
+        for &(latency, count) in &self.buckets {
+            if n < count {
+                return Duration::from_micros(latency);
+            }
+            n -= count;
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::GlobalRng;
+
+    fn mean(latency: &Latency, n: u32) -> Duration {
+        let mut rng = GlobalRng::new_with_seed(1);
+        (0..n).map(|_| latency.sample(&mut rng)).sum::<Duration>() / n
+    }
+
+    #[test]
+    fn parse() {
+        let parse = |s: &str| toml::from_str::<Latency>(s).unwrap();
+        let ms = Duration::from_millis;
+        assert_eq!(
+            parse("start = { secs = 0, nanos = 1000000 }\nend = { secs = 0, nanos = 2000000 }"),
+            Latency::from(ms(1)..ms(2))
+        );
+        let latency = parse(
+            r#"
+            type = "pareto"
+            scale = "1ms"
+            shape = 1.5
+            spike = { probability = 0.01, type = "constant", value = "1s" }
+            "#,
+        );
+        assert_eq!(
+            latency,
+            Latency {
+                distribution: Distribution::Pareto {
+                    scale: ms(1),
+                    shape: 1.5
+                },
+                spike: Some(Spike {
+                    probability: 0.01,
+                    distribution: Distribution::Constant { value: ms(1000) },
+                }),
+            }
+        );
+        let json = serde_json::to_string(&latency).unwrap();
+        assert_eq!(serde_json::from_str::<Latency>(&json).unwrap(), latency);
+        assert!(toml::from_str::<Latency>("type = \"normal\"").is_err());
+
+        // short forms of uniform and constant distributions
+        let latency = Latency::from(ms(1)..ms(2));
+        assert_eq!(serde_json::to_string(&latency).unwrap(), r#""1ms..2ms""#);
+        assert_eq!(
+            serde_json::from_str::<Latency>(r#""1ms..2ms""#).unwrap(),
+            latency
+        );
+        assert_eq!(
+            serde_json::from_str::<Latency>(r#""2.5ms""#).unwrap(),
+            Distribution::Constant {
+                value: Duration::from_micros(2500)
+            }
+            .into()
+        );
+        assert!(serde_json::from_str::<Latency>(r#""1ms..""#).is_err());
+
+        let path = std::env::temp_dir().join(format!("madsim-histogram-{}", std::process::id()));
+        std::fs::write(&path, "# latency_us count\n500 3\n\n2000, 1\n").unwrap();
+        let latency = parse(&format!(
+            "type = \"histogram\"\npath = {:?}",
+            path.display().to_string()
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            latency.distribution,
+            Distribution::Histogram(Histogram {
+                buckets: vec![(500, 3), (2000, 1)]
+            })
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let check = |s: &str| toml::from_str::<Latency>(s).unwrap().validate("latency");
+        assert_eq!(check("start = \"1ms\"\nend = \"1ms\""), Ok(()));
+        let err = check("start = \"2ms\"\nend = \"1ms\"").unwrap_err();
+        assert_eq!(err.key(), Some("latency"));
+        let err = check("type = \"pareto\"\nscale = \"1ms\"\nshape = 0.0").unwrap_err();
+        assert_eq!(err.key(), Some("latency.shape"));
+        let err = check(
+            "type = \"log_normal\"\nmedian = \"1ms\"\nsigma = 1.0\n\
+             spike = { probability = 2.0, type = \"constant\", value = \"1s\" }",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value for `latency.spike.probability`: 2 is not a possibility in [0, 1]"
+        );
+    }
+
+    #[test]
+    fn sample() {
+        let ms = Duration::from_millis;
+        let normal = Latency::from(Distribution::Normal {
+            mean: ms(10),
+            std_dev: ms(2),
+        });
+        let m = mean(&normal, 10000);
+        assert!(m > ms(9) && m < ms(11), "{m:?}");
+
+        let log_normal = Latency::from(Distribution::LogNormal {
+            median: ms(10),
+            sigma: 0.5,
+        });
+        // the mean is exp(μ + σ²/2)
+        let m = mean(&log_normal, 10000);
+        assert!(m > ms(10) && m < ms(12), "{m:?}");
+
+        let pareto = Distribution::Pareto {
+            scale: ms(1),
+            shape: 2.0,
+        };
+        let mut rng = GlobalRng::new_with_seed(1);
+        assert!((0..1000).all(|_| pareto.sample(&mut rng) >= ms(1)));
+
+        let histogram = Latency::from(Distribution::Histogram(Histogram {
+            buckets: vec![(1000, 1), (3000, 1)],
+        }));
+        let m = mean(&histogram, 10000);
+        assert!(m > ms(1900) / 1000 && m < ms(2100) / 1000, "{m:?}");
+        let empty = Distribution::Histogram(Histogram { buckets: vec![] });
+        assert_eq!(empty.sample(&mut rng), Duration::ZERO);
+
+        let spiky = Latency {
+            distribution: Distribution::Constant { value: ms(1) },
+            spike: Some(Spike {
+                probability: 0.01,
+                distribution: Distribution::Constant { value: ms(100) },
+            }),
+        };
+        let m = mean(&spiky, 10000);
+        assert!(m > ms(1) && m < ms(3), "{m:?}");
+
+        // deterministic
+        let mut rng1 = GlobalRng::new_with_seed(7);
+        let mut rng2 = GlobalRng::new_with_seed(7);
+        for _ in 0..100 {
+            assert_eq!(pareto.sample(&mut rng1), pareto.sample(&mut rng2));
+        }
+    }
+}
@@ -46,6 +46,7 @@ use std::{
 use tokio::sync::{mpsc, oneshot};
 
 use crate::{
+    determinism::Event,
     plugin,
     rand::{GlobalRng, Rng},
     task::{NodeId, TaskNodeHandle},
@@ -53,7 +54,9 @@ use crate::{
 };
 
 mod addr;
+mod dns;
 mod endpoint;
+mod latency;
 mod network;
 #[cfg(feature = "rpc")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rpc")))]
@@ -62,9 +65,12 @@ pub mod tcp;
 mod udp;
 
 pub use self::addr::{lookup_host, ToSocketAddrs};
+pub use self::dns::{Dns, DnsFailure};
 pub use self::endpoint::{Endpoint, Receiver, Sender};
-pub use self::network::{Config, Stat};
-use self::network::{IpProtocol, Network, Socket};
+pub use self::latency::{Distribution, Histogram, Latency, Spike};
+pub(crate) use self::network::IpProtocol;
+pub use self::network::{Config, LinkConfig, LinkRule, Stat};
+use self::network::{Network, Socket};
 pub use self::tcp::{TcpListener, TcpStream};
 pub use self::udp::UdpSocket;
 
@@ -77,6 +83,7 @@ pub use self::udp::UdpSocket;
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
 pub struct NetSim {
     network: Mutex<Network>,
+    dns: Mutex<dns::DnsTable>,
     rand: GlobalRng,
     time: TimeHandle,
     task: TaskNodeHandle,
@@ -90,20 +97,20 @@ type PayloadReceiver = mpsc::UnboundedReceiver<Payload>;
 
 impl plugin::Simulator for NetSim {
     fn new(_rand: &GlobalRng, _time: &TimeHandle, _config: &crate::Config) -> Self {
-        unreachable!()
+        unreachable!("NetSim is created with the context")
     }
 
-    fn new1(
-        rand: &GlobalRng,
-        time: &TimeHandle,
-        task: &TaskNodeHandle,
-        config: &crate::Config,
-    ) -> Self {
+    fn with_context(ctx: &plugin::Context<'_>) -> Self {
         NetSim {
-            network: Mutex::new(Network::new(rand.clone(), config.net.clone())),
-            rand: rand.clone(),
-            time: time.clone(),
-            task: task.clone(),
+            network: Mutex::new(Network::new(
+                ctx.rand().clone(),
+                ctx.time().clone(),
+                ctx.config().net.clone(),
+            )),
+            dns: Default::default(),
+            rand: ctx.rand().clone(),
+            time: ctx.time().clone(),
+            task: ctx.task().clone(),
         }
     }
 
@@ -112,9 +119,16 @@ impl plugin::Simulator for NetSim {
         network.insert_node(id);
     }
 
-    fn reset_node(&self, id: NodeId) {
+    fn on_kill(&self, id: NodeId, _kind: plugin::ResetKind) {
         self.reset_node(id);
     }
+
+    fn on_remove_node(&self, id: NodeId) {
+        let mut network = self.network.lock();
+        network.remove_node(id);
+        drop(network);
+        self.dns.lock().remove_node(id);
+    }
 }
 
 impl NetSim {
@@ -124,6 +138,10 @@ impl NetSim {
     }
 
     /// Update network configurations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the updated config is invalid.
     pub fn update_config(&self, f: impl FnOnce(&mut Config)) {
         let mut network = self.network.lock();
         network.update_config(f);
@@ -138,11 +156,145 @@ impl NetSim {
     }
 
     /// Set IP address of a node.
+    ///
+    /// If the IP address is changed, all connections from or to the old address
+    /// are closed. Sockets bound to `0.0.0.0` keep working on the new address.
     pub fn set_ip(&self, node: NodeId, ip: IpAddr) {
         let mut network = self.network.lock();
         network.set_ip(node, ip);
     }
 
+    /// Get IP address of a node.
+    pub fn get_ip(&self, node: NodeId) -> Option<IpAddr> {
+        let network = self.network.lock();
+        network.get_ip(node)
+    }
+
+    /// Set the zone and region of a node.
+    pub(crate) fn set_location(&self, node: NodeId, zone: Option<String>, region: Option<String>) {
+        let mut network = self.network.lock();
+        network.set_location(node, zone, region);
+    }
+
+    /// Returns the zone and region of a node.
+    pub(crate) fn get_location(&self, node: NodeId) -> (Option<String>, Option<String>) {
+        let network = self.network.lock();
+        network.get_location(node)
+    }
+
+    /// Set the latency and packet loss of links between two nodes in both directions.
+    ///
+    /// This overrides the [`links`](Config::links) rules and the global configurations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a probability is out of `[0, 1]`, the bandwidth is zero, or the
+    /// latency histogram is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{net::{LinkConfig, NetSim}, plugin, runtime::Runtime, time::Duration};
+    ///
+    /// let rt = Runtime::new();
+    /// let (id1, id2) = (rt.create_node().build().id(), rt.create_node().build().id());
+    /// rt.block_on(async move {
+    ///     let net = plugin::simulator::<NetSim>();
+    ///     let config = LinkConfig {
+    ///         packet_loss_rate: 0.0,
+    ///         send_latency: (Duration::from_millis(80)..Duration::from_millis(100)).into(),
+    ///         bandwidth: Some(10_000_000),
+    ///     };
+    ///     net.set_link_config(id1, id2, config.clone());
+    ///     assert_eq!(net.link_config(id2, id1), config);
+    /// });
+    /// ```
+    pub fn set_link_config(&self, node1: NodeId, node2: NodeId, config: LinkConfig) {
+        let mut network = self.network.lock();
+        network.set_link_config(node1, node2, Some(config));
+    }
+
+    /// Remove the link configuration set by [`set_link_config`](NetSim::set_link_config).
+    pub fn reset_link_config(&self, node1: NodeId, node2: NodeId) {
+        let mut network = self.network.lock();
+        network.set_link_config(node1, node2, None);
+    }
+
+    /// Returns the latency and packet loss of the link from `src` to `dst`.
+    pub fn link_config(&self, src: NodeId, dst: NodeId) -> LinkConfig {
+        let mut network = self.network.lock();
+        LinkConfig::clone(&network.link_config(src, dst))
+    }
+
+    /// Set the bandwidth of the network interface of a node in bytes per second.
+    ///
+    /// If `bandwidth` is `None`, the node uses [`Config::nic_bandwidth`].
+    pub fn set_nic_bandwidth(&self, node: NodeId, bandwidth: Option<u64>) {
+        let mut network = self.network.lock();
+        network.set_nic_bandwidth(node, bandwidth);
+    }
+
+    /// Returns the number of bytes waiting in the queue of the network interface of a node.
+    pub fn nic_queue_bytes(&self, node: NodeId) -> u64 {
+        let network = self.network.lock();
+        network.nic_queue_bytes(node)
+    }
+
+    /// Returns the number of bytes waiting in the queue of the link from `src` to `dst`.
+    pub fn link_queue_bytes(&self, src: NodeId, dst: NodeId) -> u64 {
+        let mut network = self.network.lock();
+        network.link_queue_bytes(src, dst)
+    }
+
+    /// Returns the simulated DNS.
+    pub fn dns(&self) -> Dns<'_> {
+        Dns { net: self }
+    }
+
+    /// Register a hostname of the node to the DNS.
+    pub(crate) fn add_hostname(&self, node: NodeId, name: &str) {
+        self.dns.lock().add_host(name, node);
+    }
+
+    /// Returns all hostnames of the node registered to the DNS.
+    pub(crate) fn hostnames(&self, node: NodeId) -> Vec<String> {
+        self.dns.lock().hostnames(node)
+    }
+
+    /// Resolve the host name by the DNS.
+    pub(crate) async fn lookup_host(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        if host.eq_ignore_ascii_case("localhost") {
+            return self.dns().lookup(host);
+        }
+        let node = plugin::node();
+        let (server, failure) = {
+            let table = self.dns.lock();
+            (table.server().unwrap_or(node), table.failure(host))
+        };
+        for _ in 0..dns::ATTEMPTS {
+            let rtt = {
+                let mut network = self.network.lock();
+                let query = network.test_link(node, server, dns::PACKET_SIZE);
+                let response = network.test_link(server, node, dns::PACKET_SIZE);
+                query.zip(response).map(|(a, b)| a + b)
+            };
+            match rtt {
+                Some(rtt) if failure != Some(DnsFailure::Timeout) => {
+                    self.time.sleep(rtt).await;
+                    if failure == Some(DnsFailure::NxDomain) {
+                        return Err(dns::not_found(host));
+                    }
+                    return self.dns().lookup(host);
+                }
+                _ => self.time.sleep(dns::TIMEOUT).await,
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("failed to lookup address information: {host}: timed out"),
+        ))
+    }
+
     /// Connect a node to the network.
     pub fn connect(&self, id: NodeId) {
         let mut network = self.network.lock();
@@ -171,7 +323,8 @@ impl NetSim {
 
     /// Delay a small random time and probably inject failure.
     async fn rand_delay(&self) -> io::Result<()> {
-        let delay = Duration::from_micros(self.rand.with(|rng| rng.gen_range(0..5)));
+        let delay = (self.rand.recorder())
+            .delay(|| Duration::from_micros(self.rand.with(|rng| rng.gen_range(0..5))));
         self.time.sleep(delay).await;
         // TODO: inject failure
         Ok(())
@@ -185,11 +338,19 @@ impl NetSim {
         dst: SocketAddr,
         protocol: IpProtocol,
         msg: Payload,
+        size: u64,
     ) -> io::Result<()> {
         self.rand_delay().await?;
-        if let Some((ip, _, socket, latency)) = self.network.lock().try_send(node, dst, protocol) {
+        let res = self.network.lock().try_send(node, dst, protocol, size);
+        if let Some((ip, _, socket, latency)) = res {
             trace!("delay: {latency:?}");
+            let rand = self.rand.clone();
             self.time.add_timer(latency, move || {
+                rand.log_event(|| Event::Deliver {
+                    node,
+                    dst,
+                    protocol,
+                });
                 socket.deliver((ip, port).into(), dst, msg);
             });
         }
@@ -206,40 +367,52 @@ impl NetSim {
         protocol: IpProtocol,
     ) -> io::Result<(PayloadSender, PayloadReceiver, SocketAddr)> {
         self.rand_delay().await?;
-        let (ip, dst_node, socket, latency) = (self.network.lock().try_send(node, dst, protocol))
-            .ok_or(io::Error::new(
-            io::ErrorKind::ConnectionRefused,
-            "connection refused",
-        ))?;
+        let (ip, dst_node, socket, latency) =
+            (self.network.lock().try_send(node, dst, protocol, 0)).ok_or(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "connection refused",
+            ))?;
         let src = (ip, port).into();
         let (tx1, rx1) = self.channel(node, dst, protocol);
         let (tx2, rx2) = self.channel(dst_node, src, protocol);
         trace!("delay: {latency:?}");
+        let rand = self.rand.clone();
         self.time.add_timer(latency, move || {
+            rand.log_event(|| Event::Deliver {
+                node,
+                dst,
+                protocol,
+            });
             socket.new_connection(src, dst, tx2, rx1);
         });
         Ok((tx1, rx2, src))
     }
 
     /// Create a reliable, ordered channel between two endpoints.
-    fn channel<T: Send + 'static>(
+    fn channel(
         self: &Arc<Self>,
         node: NodeId,
         dst: SocketAddr,
         protocol: IpProtocol,
-    ) -> (mpsc::UnboundedSender<T>, mpsc::UnboundedReceiver<T>) {
-        let (tx1, mut rx1) = mpsc::unbounded_channel::<T>();
-        let (tx2, rx2) = mpsc::unbounded_channel::<T>();
+    ) -> (PayloadSender, PayloadReceiver) {
+        let (tx1, mut rx1) = mpsc::unbounded_channel::<Payload>();
+        let (tx2, rx2) = mpsc::unbounded_channel::<Payload>();
         let net = self.clone();
         let handle = self.task.spawn(async move {
             while let Some(msg) = rx1.recv().await {
+                let size = payload_size(&msg);
                 // wait for link available
                 let mut wait = Duration::from_millis(1);
                 loop {
-                    let res = net.network.lock().try_send(node, dst, protocol);
+                    let res = net.network.lock().try_send(node, dst, protocol, size);
                     match res {
                         Some((_, _, _, latency)) => {
                             net.time.sleep(latency).await;
+                            net.rand.log_event(|| Event::Deliver {
+                                node,
+                                dst,
+                                protocol,
+                            });
                             break;
                         }
                         None => {
@@ -256,11 +429,30 @@ impl NetSim {
             }
             // sender is closed. propagate the close to the receiver.
         });
-        self.network.lock().abort_task_on_reset(node, handle);
+        self.network.lock().abort_task_on_reset(node, dst, handle);
         (tx1, rx2)
     }
 }
 
+/// Returns the number of bytes of a message.
+///
+/// Only bytes in `Vec<u8>` and `Bytes`, optionally tagged, are counted.
+/// Other messages are considered to be empty, so senders of typed messages
+/// like RPC should pass the size explicitly.
+pub(crate) fn payload_size(msg: &Payload) -> u64 {
+    if let Some(data) = msg.downcast_ref::<Vec<u8>>() {
+        data.len() as u64
+    } else if let Some(data) = msg.downcast_ref::<bytes::Bytes>() {
+        data.len() as u64
+    } else if let Some((_, data)) = msg.downcast_ref::<(u64, Payload)>() {
+        payload_size(data)
+    } else if let Some((_, data)) = msg.downcast_ref::<(u64, bytes::Bytes)>() {
+        data.len() as u64
+    } else {
+        0
+    }
+}
+
 /// An RAII structure used to release the bound port.
 pub(crate) struct BindGuard {
     net: Arc<NetSim>,
@@ -1,7 +1,10 @@
-use super::{Payload, PayloadReceiver, PayloadSender};
+use super::{Latency, Payload, PayloadReceiver, PayloadSender};
 use crate::{
+    determinism::Event,
     rand::*,
+    sim::config::error::{check_probability, ConfigError},
     task::{JoinHandle, NodeId},
+    time::TimeHandle,
 };
 use async_task::FallibleTask;
 use log::*;
@@ -12,7 +15,6 @@ use std::{
     hash::{Hash, Hasher},
     io,
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    ops::Range,
     sync::Arc,
     time::Duration,
 };
@@ -23,6 +25,7 @@ use std::{
 /// It doesn't care about specific communication protocol.
 pub(crate) struct Network {
     rand: GlobalRng,
+    time: TimeHandle,
     config: Config,
     stat: Stat,
     nodes: HashMap<NodeId, Node>,
@@ -30,6 +33,14 @@ pub(crate) struct Network {
     addr_to_node: HashMap<IpAddr, NodeId>,
     clogged_node: HashSet<NodeId>,
     clogged_link: HashSet<(NodeId, NodeId)>,
+    /// Link configurations set at runtime, by the pair of nodes in ascending order.
+    link_config: HashMap<(NodeId, NodeId), LinkConfig>,
+    /// Resolved link configurations, by the pair of nodes in ascending order.
+    ///
+    /// Cleared when the location of a node or any link configuration changes.
+    resolved_links: HashMap<(NodeId, NodeId), Arc<LinkConfig>>,
+    /// Queues of directed links with limited bandwidth.
+    link_queues: HashMap<(NodeId, NodeId), Queue>,
 }
 
 /// A node in the network.
@@ -39,10 +50,19 @@ struct Node {
     ///
     /// NOTE: now a node can have at most one IP address.
     ip: Option<IpAddr>,
+    /// The zone and region of the node.
+    zone: Option<String>,
+    region: Option<String>,
+    /// The bandwidth of the NIC, overriding the one in config.
+    nic_bandwidth: Option<u64>,
+    /// The egress queue of the NIC.
+    nic_queue: Queue,
     /// Sockets in the node.
     sockets: HashMap<(SocketAddr, IpProtocol), Arc<dyn Socket>>,
-    /// Used to close channels when the node is reset.
-    tasks: Vec<FallibleTask<()>>,
+    /// Used to close channels when the node is reset or its IP is changed.
+    ///
+    /// Each channel sends messages from this node to the destination address.
+    tasks: Vec<(SocketAddr, FallibleTask<std::thread::Result<()>>)>,
 }
 
 #[non_exhaustive]
@@ -69,15 +89,67 @@ pub trait Socket: Any + Send + Sync {
 }
 
 /// Network configurations.
+///
+/// The latency and packet loss can be set for pairs of zones or regions by
+/// [`links`](Config::links). For example, with nodes in zones `a1` and `a2` of
+/// region `a` and zone `b1` of region `b`:
+///
+/// ```toml
+/// [net]
+/// send_latency = "1ms..2ms"
+///
+/// # across zones in region `a`
+/// [[net.links]]
+/// between = ["a1", "a2"]
+/// send_latency = "2ms..5ms"
+///
+/// # across regions
+/// [[net.links]]
+/// between = ["a", "b"]
+/// packet_loss_rate = 0.01
+///
+/// [net.links.send_latency]
+/// type = "log_normal"
+/// median = "80ms"
+/// sigma = 0.2
+/// spike = { probability = 0.001, type = "constant", value = "1s" }
+/// ```
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Config {
     /// Possibility of packet loss.
     #[serde(default)]
     pub packet_loss_rate: f64,
-    /// The latency range of sending packets.
+    /// The latency of sending packets.
+    ///
+    /// See [`Latency`] for the distributions.
     #[serde(default = "default_send_latency")]
-    pub send_latency: Range<Duration>,
+    pub send_latency: Latency,
+    /// The bandwidth of each link in bytes per second.
+    ///
+    /// By default, the bandwidth is unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<u64>,
+    /// The bandwidth of the network interface of each node in bytes per second.
+    ///
+    /// It is shared by all outgoing messages of the node.
+    /// By default, the bandwidth is unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nic_bandwidth: Option<u64>,
+    /// The capacity in bytes of the queue of each network interface and link.
+    ///
+    /// Datagrams are dropped when the queue is full, and reliable connections
+    /// retry later. A message larger than the capacity is only sent when the
+    /// queue is empty. By default, the queues are unbounded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_capacity: Option<u64>,
+    /// Link configurations between zones or regions.
+    ///
+    /// A rule matching both nodes by zone overrides the one matching by region,
+    /// and a later rule overrides earlier ones that are equally specific.
+    /// Links without a matching rule use the configurations above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkRule>,
 }
 
 impl Default for Config {
@@ -85,12 +157,37 @@ impl Default for Config {
         Config {
             packet_loss_rate: 0.0,
             send_latency: default_send_latency(),
+            bandwidth: None,
+            nic_bandwidth: None,
+            queue_capacity: None,
+            links: vec![],
         }
     }
 }
 
-const fn default_send_latency() -> Range<Duration> {
-    Duration::from_millis(1)..Duration::from_millis(10)
+impl Config {
+    /// Returns the default link configuration.
+    fn default_link(&self) -> LinkConfig {
+        LinkConfig {
+            packet_loss_rate: self.packet_loss_rate,
+            send_latency: self.send_latency.clone(),
+            bandwidth: self.bandwidth,
+        }
+    }
+
+    /// Check that all values are in range.
+    pub(crate) fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        self.default_link().validate(key)?;
+        check_bandwidth(&format!("{key}.nic_bandwidth"), self.nic_bandwidth)?;
+        for (i, rule) in self.links.iter().enumerate() {
+            rule.link.validate(&format!("{key}.links[{i}]"))?;
+        }
+        Ok(())
+    }
+}
+
+fn default_send_latency() -> Latency {
+    (Duration::from_millis(1)..Duration::from_millis(10)).into()
 }
 
 #[allow(clippy::derive_hash_xor_eq)]
@@ -98,6 +195,86 @@ impl Hash for Config {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.packet_loss_rate.to_bits().hash(state);
         self.send_latency.hash(state);
+        self.bandwidth.hash(state);
+        self.nic_bandwidth.hash(state);
+        self.queue_capacity.hash(state);
+        self.links.hash(state);
+    }
+}
+
+/// The latency and packet loss of a link.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct LinkConfig {
+    /// Possibility of packet loss.
+    #[serde(default)]
+    pub packet_loss_rate: f64,
+    /// The latency of sending packets.
+    #[serde(default = "default_send_latency")]
+    pub send_latency: Latency,
+    /// The bandwidth in bytes per second. `None` means unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<u64>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Config::default().default_link()
+    }
+}
+
+impl LinkConfig {
+    pub(crate) fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        check_probability(&format!("{key}.packet_loss_rate"), self.packet_loss_rate)?;
+        self.send_latency.validate(&format!("{key}.send_latency"))?;
+        check_bandwidth(&format!("{key}.bandwidth"), self.bandwidth)
+    }
+}
+
+fn check_bandwidth(key: &str, bandwidth: Option<u64>) -> Result<(), ConfigError> {
+    if bandwidth == Some(0) {
+        return Err(ConfigError::invalid(key, "should be positive"));
+    }
+    Ok(())
+}
+
+#[allow(clippy::derive_hash_xor_eq)]
+impl Hash for LinkConfig {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.packet_loss_rate.to_bits().hash(state);
+        self.send_latency.hash(state);
+        self.bandwidth.hash(state);
+    }
+}
+
+/// The link configuration between two zones or regions, in both directions.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Hash)]
+pub struct LinkRule {
+    /// The names of two zones or regions. They can be the same for links inside it.
+    pub between: [String; 2],
+    /// The configuration of links between them.
+    #[serde(flatten)]
+    pub link: LinkConfig,
+}
+
+impl LinkRule {
+    /// Returns how specifically the rule matches the link between two nodes,
+    /// or `None` if it doesn't match.
+    fn matches(&self, a: &Node, b: &Node) -> Option<u8> {
+        let [x, y] = &self.between;
+        let score = |node: &Node, name: &String| {
+            if node.zone.as_ref() == Some(name) {
+                Some(2)
+            } else if node.region.as_ref() == Some(name) {
+                Some(1)
+            } else {
+                None
+            }
+        };
+        let forward = score(a, x).zip(score(b, y)).map(|(s1, s2)| s1 + s2);
+        let backward = score(a, y).zip(score(b, x)).map(|(s1, s2)| s1 + s2);
+        forward.max(backward)
     }
 }
 
@@ -107,23 +284,73 @@ impl Hash for Config {
 pub struct Stat {
     /// Total number of messages.
     pub msg_count: u64,
+    /// The number of messages dropped because a queue is full.
+    pub overflow_count: u64,
+    /// The maximum number of bytes in a queue.
+    pub max_queue_bytes: u64,
+}
+
+/// A FIFO queue of bytes transmitted at a limited bandwidth.
+#[derive(Debug, Default)]
+struct Queue {
+    /// The time since the start of simulation when all bytes in the queue are transmitted.
+    busy_until: Duration,
+}
+
+impl Queue {
+    /// Returns the number of bytes in the queue at `now`.
+    fn bytes(&self, now: Duration, bandwidth: u64) -> u64 {
+        let backlog = self.busy_until.saturating_sub(now);
+        (backlog.as_nanos() * bandwidth as u128 / 1_000_000_000) as u64
+    }
+
+    /// Returns whether `size` more bytes fit in the queue at `now`.
+    ///
+    /// A message larger than the capacity fits in an empty queue,
+    /// otherwise it could never be sent.
+    fn fits(&self, now: Duration, size: u64, bandwidth: u64, capacity: Option<u64>) -> bool {
+        match capacity {
+            Some(_) if self.busy_until <= now => true,
+            Some(capacity) => self.bytes(now, bandwidth) + size <= capacity,
+            None => true,
+        }
+    }
+
+    /// Push `size` bytes arriving at `arrival` into the queue.
+    ///
+    /// Returns the time when they are transmitted.
+    fn push(&mut self, arrival: Duration, size: u64, bandwidth: u64) -> Duration {
+        let nanos = size as u128 * 1_000_000_000 / bandwidth.max(1) as u128;
+        self.busy_until = self.busy_until.max(arrival) + Duration::from_nanos(nanos as u64);
+        self.busy_until
+    }
 }
 
 impl Network {
-    pub fn new(rand: GlobalRng, config: Config) -> Self {
+    pub fn new(rand: GlobalRng, time: TimeHandle, config: Config) -> Self {
         Self {
             rand,
+            time,
             config,
             stat: Stat::default(),
             nodes: HashMap::new(),
             addr_to_node: HashMap::new(),
             clogged_node: HashSet::new(),
             clogged_link: HashSet::new(),
+            link_config: HashMap::new(),
+            resolved_links: HashMap::new(),
+            link_queues: HashMap::new(),
         }
     }
 
     pub fn update_config(&mut self, f: impl FnOnce(&mut Config)) {
-        f(&mut self.config);
+        let mut config = self.config.clone();
+        f(&mut config);
+        if let Err(e) = config.validate("net") {
+            panic!("{e}");
+        }
+        self.config = config;
+        self.resolved_links.clear();
     }
 
     pub fn stat(&self) -> &Stat {
@@ -143,17 +370,146 @@ impl Network {
         node.tasks.clear();
     }
 
+    /// Remove a node. Its IP address is released and all sockets are closed.
+    pub fn remove_node(&mut self, id: NodeId) {
+        debug!("remove: {id}");
+        let node = self.nodes.remove(&id).expect("node not found");
+        if let Some(ip) = node.ip {
+            self.addr_to_node.remove(&ip);
+        }
+        self.clogged_node.remove(&id);
+        self.clogged_link
+            .retain(|&(src, dst)| src != id && dst != id);
+        self.link_config.retain(|&(a, b), _| a != id && b != id);
+        self.resolved_links.retain(|&(a, b), _| a != id && b != id);
+        self.link_queues.retain(|&(a, b), _| a != id && b != id);
+    }
+
+    /// Set the IP address of a node.
+    ///
+    /// If the node already has an IP address, all connections from or to the
+    /// old address are closed. Sockets bound to the unspecified address keep
+    /// working on the new address, while sockets bound to the old address are
+    /// no longer reachable.
     pub fn set_ip(&mut self, id: NodeId, ip: IpAddr) {
         debug!("set-ip: {id}: {ip}");
+        if let Some(&old_node) = self.addr_to_node.get(&ip) {
+            if old_node == id {
+                return;
+            }
+            panic!("IP conflict: {ip} {old_node}");
+        }
         let node = self.nodes.get_mut(&id).expect("node not found");
-        if let Some(old_ip) = node.ip.replace(ip) {
-            self.addr_to_node.remove(&old_ip);
+        let old_ip = match node.ip.replace(ip) {
+            Some(old_ip) => old_ip,
+            None => {
+                self.addr_to_node.insert(ip, id);
+                return;
+            }
+        };
+        self.addr_to_node.remove(&old_ip);
+        self.addr_to_node.insert(ip, id);
+        // close connections from the old address
+        node.tasks.retain(|(dst, _)| dst.ip().is_loopback());
+        // close connections to the old address
+        for node in self.nodes.values_mut() {
+            node.tasks.retain(|(dst, _)| dst.ip() != old_ip);
         }
-        let old_node = self.addr_to_node.insert(ip, id);
-        if let Some(old_node) = old_node {
-            panic!("IP conflict: {ip} {old_node}");
+    }
+
+    pub fn get_ip(&self, id: NodeId) -> Option<IpAddr> {
+        self.nodes.get(&id)?.ip
+    }
+
+    /// Set the zone and region of a node.
+    pub fn set_location(&mut self, id: NodeId, zone: Option<String>, region: Option<String>) {
+        debug!("set-location: {id}: zone={zone:?} region={region:?}");
+        let node = self.nodes.get_mut(&id).expect("node not found");
+        node.zone = zone;
+        node.region = region;
+        self.resolved_links.retain(|&(a, b), _| a != id && b != id);
+    }
+
+    /// Returns the zone and region of a node.
+    pub fn get_location(&self, id: NodeId) -> (Option<String>, Option<String>) {
+        match self.nodes.get(&id) {
+            Some(node) => (node.zone.clone(), node.region.clone()),
+            None => (None, None),
+        }
+    }
+
+    /// Set the configuration of links between two nodes in both directions.
+    ///
+    /// If `config` is `None`, the link uses the configuration of the network.
+    pub fn set_link_config(&mut self, a: NodeId, b: NodeId, config: Option<LinkConfig>) {
+        assert!(self.nodes.contains_key(&a));
+        assert!(self.nodes.contains_key(&b));
+        debug!("set-link-config: {a} <-> {b}: {config:?}");
+        if let Some(Err(e)) = config.as_ref().map(|config| config.validate("link")) {
+            panic!("{e}");
+        }
+        let key = (a.min(b), a.max(b));
+        self.resolved_links.remove(&key);
+        match config {
+            Some(config) => self.link_config.insert(key, config),
+            None => self.link_config.remove(&key),
+        };
+    }
+
+    /// Set the bandwidth of the NIC of a node.
+    ///
+    /// If `bandwidth` is `None`, the node uses the bandwidth in the config.
+    pub fn set_nic_bandwidth(&mut self, id: NodeId, bandwidth: Option<u64>) {
+        let node = self.nodes.get_mut(&id).expect("node not found");
+        node.nic_bandwidth = bandwidth;
+    }
+
+    /// Returns the number of bytes in the NIC queue of a node.
+    pub fn nic_queue_bytes(&self, id: NodeId) -> u64 {
+        let node = self.nodes.get(&id).expect("node not found");
+        match node.nic_bandwidth.or(self.config.nic_bandwidth) {
+            Some(bandwidth) => node.nic_queue.bytes(self.time.elapsed(), bandwidth),
+            None => 0,
+        }
+    }
+
+    /// Returns the number of bytes in the queue of the link from `src` to `dst`.
+    pub fn link_queue_bytes(&mut self, src: NodeId, dst: NodeId) -> u64 {
+        let bandwidth = self.link_config(src, dst).bandwidth;
+        match (self.link_queues.get(&(src, dst)), bandwidth) {
+            (Some(queue), Some(bandwidth)) => queue.bytes(self.time.elapsed(), bandwidth),
+            _ => 0,
+        }
+    }
+
+    /// Returns the configuration of the link from `src` to `dst`.
+    pub fn link_config(&mut self, src: NodeId, dst: NodeId) -> Arc<LinkConfig> {
+        let key = (src.min(dst), src.max(dst));
+        if let Some(config) = self.resolved_links.get(&key) {
+            return config.clone();
+        }
+        let config = Arc::new(self.resolve_link_config(src, dst));
+        self.resolved_links.insert(key, config.clone());
+        config
+    }
+
+    fn resolve_link_config(&self, src: NodeId, dst: NodeId) -> LinkConfig {
+        if let Some(config) = self.link_config.get(&(src.min(dst), src.max(dst))) {
+            return config.clone();
+        }
+        let (a, b) = (&self.nodes[&src], &self.nodes[&dst]);
+        let mut best = None;
+        for rule in &self.config.links {
+            match (rule.matches(a, b), best) {
+                (Some(score), Some((best_score, _))) if score < best_score => {}
+                (Some(score), _) => best = Some((score, rule)),
+                (None, _) => {}
+            }
+        }
+        match best {
+            Some((_, rule)) => rule.link.clone(),
+            None => self.config.default_link(),
         }
-        // TODO: what if we change the IP when there are opening sockets?
     }
 
     pub fn clog_node(&mut self, id: NodeId) {
@@ -242,19 +598,57 @@ impl Network {
     /// Close a socket.
     pub fn close(&mut self, node: NodeId, addr: SocketAddr, protocol: IpProtocol) {
         debug!("close: {node} {addr} {protocol:?}");
-        let node = self.nodes.get_mut(&node).expect("node not found");
-        node.sockets.remove(&(addr, protocol));
+        // the node may have been removed
+        if let Some(node) = self.nodes.get_mut(&node) {
+            node.sockets.remove(&(addr, protocol));
+        }
     }
 
-    /// Returns the latency of sending a packet. If packet loss, returns `None`.
-    fn test_link(&mut self, src: NodeId, dst: NodeId) -> Option<Duration> {
-        if self.link_clogged(src, dst) || self.rand.gen_bool(self.config.packet_loss_rate) {
-            None
-        } else {
-            self.stat.msg_count += 1;
-            // TODO: special value for loopback
-            Some(self.rand.gen_range(self.config.send_latency.clone()))
+    /// Returns the latency of sending a packet of `size` bytes, including the time
+    /// waiting in queues. If packet loss or a queue overflows, returns `None`.
+    pub fn test_link(&mut self, src: NodeId, dst: NodeId, size: u64) -> Option<Duration> {
+        if self.link_clogged(src, dst) {
+            return None;
         }
+        let config = self.link_config(src, dst);
+        let mut rand = self.rand.clone();
+        let latency = self.rand.recorder().link(|| {
+            if rand.gen_bool(config.packet_loss_rate) {
+                None
+            } else {
+                // TODO: special value for loopback
+                Some(config.send_latency.sample(&mut rand))
+            }
+        })?;
+        let now = self.time.elapsed();
+        let sent = if src == dst {
+            now
+        } else {
+            let capacity = self.config.queue_capacity;
+            let mut sent = now;
+            let node = self.nodes.get_mut(&src).expect("node not found");
+            let nic = node.nic_bandwidth.or(self.config.nic_bandwidth);
+            let queues = [
+                nic.map(|bandwidth| (&mut node.nic_queue, bandwidth)),
+                (config.bandwidth)
+                    .map(|bandwidth| (self.link_queues.entry((src, dst)).or_default(), bandwidth)),
+            ];
+            // the packet is dropped without occupying any queue if one of them is full
+            let fits = (queues.iter().flatten())
+                .all(|(queue, bandwidth)| queue.fits(now, size, *bandwidth, capacity));
+            if !fits {
+                self.stat.overflow_count += 1;
+                return None;
+            }
+            for (queue, bandwidth) in queues.into_iter().flatten() {
+                sent = queue.push(sent, size, bandwidth);
+                let bytes = queue.bytes(now, bandwidth);
+                self.stat.max_queue_bytes = self.stat.max_queue_bytes.max(bytes);
+            }
+            sent
+        };
+        self.stat.msg_count += 1;
+        Some(sent - now + latency)
     }
 
     /// Resolve destination node from IP address.
@@ -287,9 +681,15 @@ impl Network {
         node: NodeId,
         dst: SocketAddr,
         protocol: IpProtocol,
+        size: u64,
     ) -> Option<(IpAddr, NodeId, Arc<dyn Socket>, Duration)> {
+        (self.rand).log_event(|| Event::Send {
+            node,
+            dst,
+            protocol,
+        });
         let dst_node = self.resolve_dest_node(node, dst, protocol)?;
-        let latency = self.test_link(node, dst_node)?;
+        let latency = self.test_link(node, dst_node, size)?;
         let sockets = &self.nodes.get(&dst_node)?.sockets;
         let ep = (sockets.get(&(dst, protocol)))
             .or_else(|| sockets.get(&((Ipv4Addr::UNSPECIFIED, dst.port()).into(), protocol)))?;
@@ -301,8 +701,9 @@ impl Network {
         Some((src_ip, dst_node, ep.clone(), latency))
     }
 
-    pub fn abort_task_on_reset(&mut self, node: NodeId, handle: JoinHandle<()>) {
+    /// Abort the channel task from `node` to `dst` when the connection is closed.
+    pub fn abort_task_on_reset(&mut self, node: NodeId, dst: SocketAddr, handle: JoinHandle<()>) {
         let node = self.nodes.get_mut(&node).expect("node not found");
-        node.tasks.push(handle.cancel_on_drop());
+        node.tasks.push((dst, handle.cancel_on_drop()));
     }
 }
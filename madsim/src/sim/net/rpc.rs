@@ -120,7 +120,8 @@ impl Endpoint {
         let req_tag = R::ID;
         let rsp_tag = random::<u64>();
         let data = Bytes::copy_from_slice(data);
-        self.send_to_raw(dst, req_tag, Box::new((rsp_tag, request, data)))
+        let size = data.len() as u64;
+        self.send_to_raw_with_size(dst, req_tag, Box::new((rsp_tag, request, data)), size)
             .await?;
         let (rsp, from) = self.recv_from_raw(rsp_tag).await?;
         assert_eq!(from, dst);
@@ -157,7 +158,9 @@ impl Endpoint {
                 let net = net.clone();
                 crate::task::spawn(async move {
                     let (rsp, data) = rsp_future.await;
-                    net.send_to_raw(from, rsp_tag, Box::new((rsp, Bytes::from(data))))
+                    let size = data.len() as u64;
+                    let msg = Box::new((rsp, Bytes::from(data)));
+                    net.send_to_raw_with_size(from, rsp_tag, msg, size)
                         .await
                         .unwrap();
                 });
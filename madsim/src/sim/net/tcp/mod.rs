@@ -57,7 +57,12 @@ pub use self::stream::*;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{net::NetSim, plugin, runtime::Runtime, time::timeout};
+    use crate::{
+        net::NetSim,
+        plugin,
+        runtime::{Handle, Runtime},
+        time::timeout,
+    };
     use std::{io::ErrorKind, net::SocketAddr, sync::Arc, time::Duration};
     use tokio::{
         io::{AsyncReadExt, AsyncWriteExt},
@@ -95,6 +100,45 @@ mod tests {
         runtime.block_on(f2).unwrap();
     }
 
+    #[test]
+    fn send_larger_than_queue_capacity() {
+        let mut config = crate::Config::default();
+        config.net.nic_bandwidth = Some(1_000_000);
+        config.net.queue_capacity = Some(100_000);
+        let runtime = Runtime::with_seed_and_config(1, config);
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime.create_node().ip(addr2.ip()).build();
+        let barrier = Arc::new(Barrier::new(2));
+        let barrier_ = barrier.clone();
+
+        node1.spawn(async move {
+            let listener = TcpListener::bind(addr1).await.unwrap();
+            barrier_.wait().await;
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // the message is sent once the queue is empty
+            stream.write_all(&[1; 200_000]).await.unwrap();
+            stream.write_all(&[2; 200_000]).await.unwrap();
+            stream.flush().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let f = node2.spawn(async move {
+            barrier.wait().await;
+            let mut stream = TcpStream::connect(addr1).await.unwrap();
+            let mut buf = vec![0; 400_000];
+            timeout(Duration::from_secs(10), stream.read_exact(&mut buf))
+                .await
+                .expect("message is never sent")
+                .unwrap();
+            assert!(buf[..200_000].iter().all(|&b| b == 1));
+            assert!(buf[200_000..].iter().all(|&b| b == 2));
+        });
+
+        runtime.block_on(f).unwrap();
+    }
+
     #[test]
     fn disconnect_and_recovery() {
         let runtime = Runtime::new();
@@ -246,4 +290,96 @@ mod tests {
         });
         runtime.block_on(f1).unwrap();
     }
+
+    #[test]
+    fn change_ip() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let addr3 = "10.0.0.3:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime
+            .create_node()
+            .ip("10.0.0.2".parse().unwrap())
+            .build();
+        let barrier = Arc::new(Barrier::new(2));
+        let barrier_ = barrier.clone();
+
+        node1.spawn(async move {
+            let listener = TcpListener::bind("0.0.0.0:1").await.unwrap();
+            barrier.wait().await;
+            let mut streams = vec![];
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                stream.write_all(b"hello").await.unwrap();
+                stream.flush().await.unwrap();
+                streams.push(stream);
+            }
+        });
+
+        let f2 = node2.spawn(async move {
+            barrier_.wait().await;
+            let mut stream = TcpStream::connect(addr1).await.unwrap();
+            let mut buf = [0; 20];
+            let len = stream.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[0..len], b"hello");
+
+            let net = plugin::simulator::<NetSim>();
+            net.set_ip(node1.id(), addr3.ip());
+            assert_eq!(node1.ip(), Some(addr3.ip()));
+
+            // connections to the old address are closed
+            let len = stream.read(&mut buf).await.expect("read should return EOF");
+            assert_eq!(len, 0);
+            let err = TcpStream::connect(addr1).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+
+            // the listener keeps working on the new address
+            let mut stream = TcpStream::connect(addr3).await.unwrap();
+            let len = stream.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[0..len], b"hello");
+        });
+
+        runtime.block_on(f2).unwrap();
+    }
+
+    #[test]
+    fn remove_node() {
+        let runtime = Runtime::new();
+        let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+        let node1 = runtime.create_node().ip(addr1.ip()).build();
+        let node2 = runtime
+            .create_node()
+            .ip("10.0.0.2".parse().unwrap())
+            .build();
+        let barrier = Arc::new(Barrier::new(2));
+        let barrier_ = barrier.clone();
+
+        node1.spawn(async move {
+            let listener = TcpListener::bind(addr1).await.unwrap();
+            barrier.wait().await;
+            let (_stream, _) = listener.accept().await.unwrap();
+            futures::future::pending::<()>().await;
+        });
+
+        let f2 = node2.spawn(async move {
+            barrier_.wait().await;
+            let mut stream = TcpStream::connect(addr1).await.unwrap();
+
+            Handle::current().remove_node(node1.id());
+            let mut buf = [0; 20];
+            let len = stream.read(&mut buf).await.expect("read should return EOF");
+            assert_eq!(len, 0);
+            let err = TcpStream::connect(addr1).await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+
+            // the IP address is released
+            let node3 = Handle::current().create_node().ip(addr1.ip()).build();
+            node3
+                .spawn(async move { TcpListener::bind(addr1).await.unwrap() })
+                .await
+                .unwrap();
+        });
+
+        runtime.block_on(f2).unwrap();
+    }
 }
@@ -15,16 +15,67 @@ use crate::{
 };
 
 /// Simulator
+///
+/// A simulator is created for each runtime, and notified of the lifecycle of
+/// every node by the `on_*` hooks. All hooks are called after the tasks of the
+/// node have been updated.
+///
+/// Custom simulators can be registered by [`Runtime::add_simulator`] or
+/// [`Builder::add_simulator`]. The latter also works with `#[madsim::test]`
+/// by the `simulators` argument.
+///
+/// [`Runtime::add_simulator`]: crate::runtime::Runtime::add_simulator
+/// [`Builder::add_simulator`]: crate::runtime::Builder::add_simulator
+///
+/// # Example
+///
+/// ```
+/// use madsim::{plugin::{self, Simulator}, rand::GlobalRng, runtime::Runtime};
+/// use madsim::{task::NodeId, time::TimeHandle, Config};
+/// use serde::Deserialize;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// #[derive(Default, Deserialize)]
+/// struct ClockConfig {
+///     drift: u64,
+/// }
+///
+/// struct ClockSim {
+///     drift: u64,
+///     kills: AtomicUsize,
+/// }
+///
+/// impl Simulator for ClockSim {
+///     fn new(_rand: &GlobalRng, _time: &TimeHandle, config: &Config) -> Self {
+///         let config: ClockConfig = config.simulator("clock").unwrap();
+///         ClockSim { drift: config.drift, kills: AtomicUsize::new(0) }
+///     }
+///
+///     fn on_kill(&self, _id: NodeId, _kind: plugin::ResetKind) {
+///         self.kills.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let config = "[simulators.clock]\ndrift = 5".parse().unwrap();
+/// let rt = Runtime::with_seed_and_config(1, config);
+/// rt.add_simulator::<ClockSim>();
+/// let node = rt.create_node().build();
+/// rt.handle().kill(node.id());
+/// rt.block_on(async {
+///     let sim = plugin::simulator::<ClockSim>();
+///     assert_eq!(sim.drift, 5);
+///     assert_eq!(sim.kills.load(Ordering::Relaxed), 1);
+/// });
+/// ```
 pub trait Simulator: Any + Send + Sync + DowncastSync {
     /// Create a new simulator.
-    ///
-    /// This will be called on the first access via [`simulator`].
     fn new(rand: &GlobalRng, time: &TimeHandle, config: &Config) -> Self
     where
         Self: Sized;
 
     // XXX: For compatibility. Merge to `new` in the next major version.
     #[doc(hidden)]
+    #[deprecated(note = "implement `with_context` instead")]
     fn new1(rand: &GlobalRng, time: &TimeHandle, _task: &TaskNodeHandle, config: &Config) -> Self
     where
         Self: Sized,
@@ -32,15 +83,115 @@ pub trait Simulator: Any + Send + Sync + DowncastSync {
         Self::new(rand, time, config)
     }
 
+    /// Create a new simulator with the context of the runtime.
+    ///
+    /// This will be called when the simulator is registered to a runtime.
+    /// By default, it calls [`new`](Simulator::new).
+    /// A simulator that needs more than `new` gets can override this,
+    /// then `new` is never called by the runtime.
+    fn with_context(ctx: &Context<'_>) -> Self
+    where
+        Self: Sized,
+    {
+        #[allow(deprecated)]
+        Self::new1(ctx.rand, ctx.time, ctx.task, ctx.config)
+    }
+
     /// Create a node.
     fn create_node(&self, _id: NodeId) {}
 
     /// Reset a node.
+    #[deprecated(note = "implement `on_kill` instead")]
     fn reset_node(&self, _id: NodeId) {}
+
+    /// Reset a node in the given way.
+    ///
+    /// By default, it calls [`reset_node`](Simulator::reset_node) for any kind of reset.
+    #[deprecated(note = "implement `on_kill` instead")]
+    fn reset_node_with(&self, id: NodeId, _kind: ResetKind) {
+        #[allow(deprecated)]
+        self.reset_node(id);
+    }
+
+    /// Called when a node is killed in the given way.
+    ///
+    /// By default, it calls [`reset_node_with`](Simulator::reset_node_with).
+    fn on_kill(&self, id: NodeId, kind: ResetKind) {
+        #[allow(deprecated)]
+        self.reset_node_with(id, kind);
+    }
+
+    /// Called when a node is started again after [`on_kill`](Simulator::on_kill).
+    fn on_restart(&self, _id: NodeId) {}
+
+    /// Called when a node is paused.
+    fn on_pause(&self, _id: NodeId) {}
+
+    /// Called when a node is resumed.
+    fn on_resume(&self, _id: NodeId) {}
+
+    /// Remove a node and release all its resources.
+    #[deprecated(note = "implement `on_remove_node` instead")]
+    fn remove_node(&self, _id: NodeId) {}
+
+    /// Called when a node is removed. All its resources should be released.
+    ///
+    /// By default, it calls [`remove_node`](Simulator::remove_node).
+    fn on_remove_node(&self, id: NodeId) {
+        #[allow(deprecated)]
+        self.remove_node(id);
+    }
 }
 
 impl_downcast!(sync Simulator);
 
+/// The context to create a simulator.
+pub struct Context<'a> {
+    pub(crate) rand: &'a GlobalRng,
+    pub(crate) time: &'a TimeHandle,
+    pub(crate) task: &'a TaskNodeHandle,
+    pub(crate) config: &'a Config,
+}
+
+impl<'a> Context<'a> {
+    /// Returns the random number generator.
+    pub fn rand(&self) -> &'a GlobalRng {
+        self.rand
+    }
+
+    /// Returns the time handle.
+    pub fn time(&self) -> &'a TimeHandle {
+        self.time
+    }
+
+    /// Returns the configuration.
+    ///
+    /// The section of a simulator can be got by [`Config::simulator`].
+    pub fn config(&self) -> &'a Config {
+        self.config
+    }
+
+    /// Returns the task handle of the supervisor node.
+    #[doc(hidden)]
+    pub fn task(&self) -> &'a TaskNodeHandle {
+        self.task
+    }
+}
+
+/// The way a node is reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ResetKind {
+    /// The process crashed or was killed.
+    ///
+    /// The machine keeps running, so data in the OS page cache survives.
+    Crash,
+    /// The machine lost power.
+    ///
+    /// All data that has not been synced to the disk is lost.
+    PowerLoss,
+}
+
 /// Get the simulator.
 pub fn simulator<S: Simulator>() -> Arc<S> {
     crate::context::current(|h| {
@@ -57,3 +208,128 @@ pub fn simulator<S: Simulator>() -> Arc<S> {
 pub fn node() -> NodeId {
     crate::context::current_node()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Builder, Handle};
+    use spin::Mutex;
+
+    #[derive(Default)]
+    struct EventSim {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Simulator for EventSim {
+        fn new(_rand: &GlobalRng, _time: &TimeHandle, _config: &Config) -> Self {
+            Default::default()
+        }
+
+        fn create_node(&self, id: NodeId) {
+            self.events.lock().push(format!("create {id}"));
+        }
+
+        fn on_kill(&self, id: NodeId, kind: ResetKind) {
+            self.events.lock().push(format!("kill {id} {kind:?}"));
+        }
+
+        fn on_restart(&self, id: NodeId) {
+            self.events.lock().push(format!("restart {id}"));
+        }
+
+        fn on_pause(&self, id: NodeId) {
+            self.events.lock().push(format!("pause {id}"));
+        }
+
+        fn on_resume(&self, id: NodeId) {
+            self.events.lock().push(format!("resume {id}"));
+        }
+
+        fn on_remove_node(&self, id: NodeId) {
+            self.events.lock().push(format!("remove {id}"));
+        }
+    }
+
+    /// A simulator implementing the hooks before `with_context` and `on_kill`.
+    #[derive(Default)]
+    struct LegacySim {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Simulator for LegacySim {
+        fn new(_rand: &GlobalRng, _time: &TimeHandle, _config: &Config) -> Self {
+            unreachable!("`new1` is called instead")
+        }
+
+        fn new1(
+            _rand: &GlobalRng,
+            _time: &TimeHandle,
+            _task: &TaskNodeHandle,
+            _config: &Config,
+        ) -> Self {
+            let sim = LegacySim::default();
+            sim.events.lock().push("new1".into());
+            sim
+        }
+
+        fn reset_node_with(&self, id: NodeId, kind: ResetKind) {
+            self.events.lock().push(format!("reset {id} {kind:?}"));
+        }
+
+        fn remove_node(&self, id: NodeId) {
+            self.events.lock().push(format!("remove {id}"));
+        }
+    }
+
+    #[test]
+    fn legacy_hooks() {
+        let mut builder = Builder::new().add_simulator::<LegacySim>();
+        builder.seed = 1;
+        builder.run(|| async {
+            let handle = Handle::current();
+            let id = handle.create_node().build().id();
+            handle.kill_process(id);
+            handle.reboot(id);
+            handle.remove_node(id);
+            let events = simulator::<LegacySim>().events.lock().clone();
+            assert_eq!(
+                events,
+                [
+                    "new1",
+                    "reset Node(1) Crash",
+                    "reset Node(1) PowerLoss",
+                    "remove Node(1)"
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn hooks() {
+        let mut builder = Builder::new().add_simulator::<EventSim>();
+        builder.seed = 1;
+        builder.run(|| async {
+            let handle = Handle::current();
+            let id = handle.create_node().build().id();
+            handle.pause(id);
+            handle.resume(id);
+            handle.kill_process(id);
+            handle.reboot(id);
+            handle.remove_node(id);
+            let events = simulator::<EventSim>().events.lock().clone();
+            assert_eq!(
+                events,
+                [
+                    "create Node(0)",
+                    "create Node(1)",
+                    "pause Node(1)",
+                    "resume Node(1)",
+                    "kill Node(1) Crash",
+                    "kill Node(1) PowerLoss",
+                    "restart Node(1)",
+                    "remove Node(1)",
+                ]
+            );
+        });
+    }
+}
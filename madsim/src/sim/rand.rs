@@ -25,9 +25,13 @@ use rand::{
     prelude::{Distribution, SmallRng},
 };
 
+use crate::determinism::{report, Entry, Event};
+use crate::replay::Recorder;
 use spin::Mutex;
 use std::cell::Cell;
+use std::panic::Location;
 use std::sync::Arc;
+use std::time::Duration;
 
 // TODO: mock `rngs` module
 
@@ -49,13 +53,22 @@ pub mod prelude {
 #[derive(Clone)]
 pub struct GlobalRng {
     inner: Arc<Mutex<Inner>>,
+    recorder: Recorder,
+    /// The location where this handle was retrieved by [`thread_rng`].
+    location: Option<&'static Location<'static>>,
 }
 
 struct Inner {
     seed: u64,
     rng: SmallRng,
-    log: Option<Vec<u8>>,
-    check: Option<(Vec<u8>, usize)>,
+    mode: Mode,
+}
+
+/// Whether to record or check the log.
+enum Mode {
+    Disabled,
+    Log(Log),
+    Check(Log, usize),
 }
 
 impl GlobalRng {
@@ -72,41 +85,78 @@ impl GlobalRng {
         let inner = Inner {
             seed,
             rng: SeedableRng::seed_from_u64(seed),
-            log: None,
-            check: None,
+            mode: Mode::Disabled,
         };
         GlobalRng {
             inner: Arc::new(Mutex::new(inner)),
+            recorder: Recorder::default(),
+            location: None,
         }
     }
 
     /// Call function on the inner RNG.
+    #[track_caller]
     pub(crate) fn with<T>(&self, f: impl FnOnce(&mut SmallRng) -> T) -> T {
+        self.with_at(Location::caller(), f)
+    }
+
+    /// Call function on the inner RNG, logging the location where the random number is used.
+    ///
+    /// Methods of [`RngCore`] are called from inside the `rand` crate, so they use
+    /// the location of [`thread_rng`] if it is known.
+    #[track_caller]
+    fn with_rng<T>(&self, f: impl FnOnce(&mut SmallRng) -> T) -> T {
+        let location = match self.location {
+            Some(location) => location,
+            None => Location::caller(),
+        };
+        self.with_at(location, f)
+    }
+
+    fn with_at<T>(
+        &self,
+        location: &'static Location<'static>,
+        f: impl FnOnce(&mut SmallRng) -> T,
+    ) -> T {
         let mut lock = self.inner.lock();
         let ret = f(&mut lock.rng);
         // log or check
-        if lock.log.is_some() || lock.check.is_some() {
+        if !matches!(lock.mode, Mode::Disabled) {
             let t = crate::time::TimeHandle::try_current().map(|t| t.elapsed());
             fn hash_u128(x: u128) -> u8 {
                 x.to_ne_bytes().iter().fold(0, |a, b| a ^ b)
             }
             let v = lock.rng.clone().gen::<u8>() ^ hash_u128(t.unwrap_or_default().as_nanos());
-            if let Some(log) = &mut lock.log {
-                log.push(v);
-            }
-            if let Some((check, i)) = &mut lock.check {
-                if check.get(*i) != Some(&v) {
-                    if let Some(time) = t {
-                        panic!("non-determinism detected at {:?}", time);
-                    }
-                    panic!("non-determinism detected");
-                }
-                *i += 1;
+            let res = lock.append(t, Some(v), || Event::Rand { location, hash: v });
+            drop(lock);
+            if let Err(msg) = res {
+                panic!("{msg}");
             }
         }
         ret
     }
 
+    /// Append an event to the full log, or check it against the expected one.
+    ///
+    /// The event is ignored unless the full log is enabled.
+    pub(crate) fn log_event(&self, event: impl FnOnce() -> Event) {
+        let mut lock = self.inner.lock();
+        if matches!(lock.mode, Mode::Disabled) {
+            return;
+        }
+        let t = crate::time::TimeHandle::try_current().map(|t| t.elapsed());
+        let res = lock.append(t, None, event);
+        drop(lock);
+        if let Err(msg) = res {
+            panic!("{msg}");
+        }
+    }
+
+    /// Returns the recorder of nondeterministic decisions.
+    pub(crate) fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
     pub(crate) fn seed(&self) -> u64 {
         let lock = self.inner.lock();
         lock.seed
@@ -114,59 +164,145 @@ impl GlobalRng {
 
     pub(crate) fn enable_check(&self, log: Log) {
         let mut lock = self.inner.lock();
-        lock.check = Some((log.0, 0));
+        lock.mode = Mode::Check(log, 0);
     }
 
-    pub(crate) fn enable_log(&self) {
+    /// Enable the log. If `full` is true, all events are recorded.
+    pub(crate) fn enable_log(&self, full: bool) {
         let mut lock = self.inner.lock();
-        lock.log = Some(Vec::new());
+        lock.mode = Mode::Log(Log(match full {
+            true => Repr::Event(Vec::new()),
+            false => Repr::Hash(Vec::new()),
+        }));
     }
 
     pub(crate) fn take_log(&self) -> Option<Log> {
         let mut lock = self.inner.lock();
-        lock.log
-            .take()
-            .or_else(|| lock.check.take().map(|(s, _)| s))
-            .map(Log)
+        match std::mem::replace(&mut lock.mode, Mode::Disabled) {
+            Mode::Disabled => None,
+            Mode::Log(log) | Mode::Check(log, _) => Some(log),
+        }
+    }
+
+    /// Finish the check. Panics if the log has not been consumed completely.
+    pub(crate) fn finish_check(&self) {
+        let mut lock = self.inner.lock();
+        let mode = std::mem::replace(&mut lock.mode, Mode::Disabled);
+        drop(lock);
+        match mode {
+            Mode::Check(Log(Repr::Hash(log)), i) if i < log.len() => {
+                panic!("non-determinism detected: the second run ended early")
+            }
+            Mode::Check(Log(Repr::Event(log)), i) if i < log.len() => {
+                panic!("{}", report(&log, i, None))
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Inner {
+    /// Append a random hash or an event to the log, or check it.
+    ///
+    /// Returns the error message on divergence, and the check is disabled.
+    fn append(
+        &mut self,
+        time: Option<Duration>,
+        hash: Option<u8>,
+        event: impl FnOnce() -> Event,
+    ) -> Result<(), String> {
+        match &mut self.mode {
+            Mode::Disabled => {}
+            Mode::Log(Log(Repr::Hash(log))) => log.extend(hash),
+            Mode::Log(Log(Repr::Event(log))) => log.push(Entry {
+                time,
+                event: event(),
+            }),
+            Mode::Check(Log(Repr::Hash(log)), i) => {
+                if hash.is_none() {
+                    return Ok(());
+                }
+                if log.get(*i) != hash.as_ref() {
+                    self.mode = Mode::Disabled;
+                    return Err(match time {
+                        Some(time) => format!("non-determinism detected at {:?}", time),
+                        None => "non-determinism detected".into(),
+                    });
+                }
+                *i += 1;
+            }
+            Mode::Check(Log(Repr::Event(log)), i) => {
+                let entry = Entry {
+                    time,
+                    event: event(),
+                };
+                if log.get(*i) != Some(&entry) {
+                    let msg = report(log, *i, Some(&entry));
+                    self.mode = Mode::Disabled;
+                    return Err(msg);
+                }
+                *i += 1;
+            }
+        }
+        Ok(())
     }
 }
 
 /// Retrieve the deterministic random number generator from the current madsim context.
+///
+/// In the determinism check, random numbers drawn from the returned generator
+/// are reported at the location where this function is called.
+#[track_caller]
 pub fn thread_rng() -> GlobalRng {
-    crate::context::current(|h| h.rand.clone())
+    let mut rng = crate::context::current(|h| h.rand.clone());
+    rng.location = Some(Location::caller());
+    rng
 }
 
 impl RngCore for GlobalRng {
+    #[track_caller]
     fn next_u32(&mut self) -> u32 {
-        self.with(|rng| rng.next_u32())
+        self.with_rng(|rng| rng.next_u32())
     }
 
+    #[track_caller]
     fn next_u64(&mut self) -> u64 {
-        self.with(|rng| rng.next_u64())
+        self.with_rng(|rng| rng.next_u64())
     }
 
+    #[track_caller]
     fn fill_bytes(&mut self, dest: &mut [u8]) {
-        self.with(|rng| rng.fill_bytes(dest))
+        self.with_rng(|rng| rng.fill_bytes(dest))
     }
 
+    #[track_caller]
     fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
-        self.with(|rng| rng.try_fill_bytes(dest))
+        self.with_rng(|rng| rng.try_fill_bytes(dest))
     }
 }
 
 /// Generates a random value using the global random number generator.
 #[inline]
+#[track_caller]
 pub fn random<T>() -> T
 where
     Standard: Distribution<T>,
 {
-    thread_rng().gen()
+    thread_rng().with(|rng| rng.gen())
 }
 
 /// Random log for determinism check.
 #[cfg_attr(docsrs, doc(cfg(madsim)))]
 #[derive(Debug, PartialEq, Eq)]
-pub struct Log(Vec<u8>);
+pub struct Log(Repr);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Repr {
+    /// A hash of every random number.
+    Hash(Vec<u8>),
+    /// All events in the simulation.
+    Event(Vec<Entry>),
+}
 
 /// Initialize std `RandomState` with specified seed.
 ///
@@ -0,0 +1,365 @@
+//! Record and replay nondeterministic decisions.
+//!
+//! A simulation makes a series of nondeterministic decisions: which task to
+//! poll next, how long a packet takes to deliver, whether a packet is lost, etc.
+//! Normally they are all derived from the random seed, so a failure can be
+//! reproduced with the same seed, until the code changes and consumes one
+//! more random number.
+//!
+//! When recording is enabled, every decision is saved into a [`Trace`].
+//! Replaying the trace forces the same decisions back in, regardless of the
+//! random number stream. If the running code no longer matches the trace,
+//! the point of divergence is reported and the rest of the simulation makes
+//! decisions as usual.
+//!
+//! # Example
+//!
+//! ```
+//! use madsim::runtime::Runtime;
+//!
+//! let rt = Runtime::new();
+//! rt.enable_record();
+//! rt.block_on(async {
+//!     madsim::task::spawn(async {}).await.unwrap();
+//! });
+//! let trace = rt.take_trace().unwrap();
+//!
+//! let rt = Runtime::with_seed_and_config(1, madsim::Config::default());
+//! rt.enable_replay(trace);
+//! rt.block_on(async {
+//!     madsim::task::spawn(async {}).await.unwrap();
+//! });
+//! ```
+
+use spin::Mutex;
+use std::{fmt, io, path::Path, str::FromStr, sync::Arc, time::Duration};
+
+/// A nondeterministic decision made by the simulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Decision {
+    /// Id of the task picked from the ready queue.
+    Pick(u64),
+    /// Time elapsed by polling a task.
+    Poll(Duration),
+    /// A small random delay in the network.
+    Delay(Duration),
+    /// Latency of a packet, or `None` if the packet is lost.
+    Link(Option<Duration>),
+    /// Time advanced to the next timer event.
+    ///
+    /// This is not a real decision, but a checkpoint to detect divergence early.
+    Timer(Duration),
+    /// Sequence number of the timer fired next among those expiring at the same time.
+    Fire(u64),
+}
+
+impl Decision {
+    fn kind(&self) -> &'static str {
+        match self {
+            Decision::Pick(_) => "pick",
+            Decision::Poll(_) => "poll",
+            Decision::Delay(_) => "delay",
+            Decision::Link(_) => "link",
+            Decision::Timer(_) => "timer",
+            Decision::Fire(_) => "fire",
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Pick(id) | Decision::Fire(id) => write!(f, "{} {id}", self.kind()),
+            Decision::Poll(d) | Decision::Delay(d) | Decision::Timer(d) => {
+                write!(f, "{} {}", self.kind(), d.as_nanos())
+            }
+            Decision::Link(Some(d)) => write!(f, "link {}", d.as_nanos()),
+            Decision::Link(None) => write!(f, "link loss"),
+        }
+    }
+}
+
+impl FromStr for Decision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) =
+            (s.split_once(' ')).ok_or_else(|| format!("invalid decision: {s:?}"))?;
+        let invalid = |_| format!("invalid decision: {s:?}");
+        let nanos = || value.parse().map(Duration::from_nanos).map_err(invalid);
+        Ok(match kind {
+            "pick" => Decision::Pick(value.parse().map_err(invalid)?),
+            "poll" => Decision::Poll(nanos()?),
+            "delay" => Decision::Delay(nanos()?),
+            "link" if value == "loss" => Decision::Link(None),
+            "link" => Decision::Link(Some(nanos()?)),
+            "timer" => Decision::Timer(nanos()?),
+            "fire" => Decision::Fire(value.parse().map_err(invalid)?),
+            _ => return Err(format!("unknown decision: {kind:?}")),
+        })
+    }
+}
+
+/// A trace of nondeterministic decisions.
+///
+/// The trace is stored as a text file, one decision per line.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Trace {
+    decisions: Vec<Decision>,
+}
+
+impl Trace {
+    /// Load a trace from file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Save the trace to file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Returns the number of decisions in the trace.
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    /// Returns true if the trace contains no decisions.
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+}
+
+impl FromStr for Trace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let decisions = (s.lines())
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                line.trim()
+                    .parse()
+                    .map_err(|e| format!("line {}: {e}", i + 1))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Trace { decisions })
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for decision in &self.decisions {
+            writeln!(f, "{decision}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Records or replays decisions.
+#[derive(Clone, Default)]
+pub(crate) struct Recorder {
+    inner: Arc<Mutex<Mode>>,
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Disabled,
+    Record(Trace),
+    Replay {
+        trace: Trace,
+        pos: usize,
+    },
+}
+
+impl Recorder {
+    pub fn enable_record(&self) {
+        *self.inner.lock() = Mode::Record(Trace::default());
+    }
+
+    pub fn enable_replay(&self, trace: Trace) {
+        *self.inner.lock() = Mode::Replay { trace, pos: 0 };
+    }
+
+    pub fn take_trace(&self) -> Option<Trace> {
+        match std::mem::take(&mut *self.inner.lock()) {
+            Mode::Disabled => None,
+            Mode::Record(trace) | Mode::Replay { trace, .. } => Some(trace),
+        }
+    }
+
+    /// Returns true if decisions are being replayed.
+    pub fn is_replaying(&self) -> bool {
+        matches!(*self.inner.lock(), Mode::Replay { .. })
+    }
+
+    /// Pick a task from the ready queue. `ready` tells whether a task id is in the queue.
+    pub fn pick(&self, ready: impl FnOnce(u64) -> bool, make: impl FnOnce() -> u64) -> u64 {
+        self.decide(make, Decision::Pick, |d| match d {
+            Decision::Pick(id) if ready(id) => Some(id),
+            _ => None,
+        })
+    }
+
+    /// Pick a timer to fire from those expiring at the same time.
+    /// `expired` tells whether a timer sequence number is among them.
+    pub fn fire(&self, expired: impl FnOnce(u64) -> bool, make: impl FnOnce() -> u64) -> u64 {
+        self.decide(make, Decision::Fire, |d| match d {
+            Decision::Fire(seq) if expired(seq) => Some(seq),
+            _ => None,
+        })
+    }
+
+    /// Decide the time elapsed by polling a task.
+    pub fn poll(&self, make: impl FnOnce() -> Duration) -> Duration {
+        self.decide(make, Decision::Poll, |d| match d {
+            Decision::Poll(d) => Some(d),
+            _ => None,
+        })
+    }
+
+    /// Decide a small random delay in the network.
+    pub fn delay(&self, make: impl FnOnce() -> Duration) -> Duration {
+        self.decide(make, Decision::Delay, |d| match d {
+            Decision::Delay(d) => Some(d),
+            _ => None,
+        })
+    }
+
+    /// Decide the latency of a packet, or `None` if the packet is lost.
+    pub fn link(&self, make: impl FnOnce() -> Option<Duration>) -> Option<Duration> {
+        self.decide(make, Decision::Link, |d| match d {
+            Decision::Link(d) => Some(d),
+            _ => None,
+        })
+    }
+
+    /// Check that time advances to the same timer event.
+    pub fn timer(&self, time: Duration) {
+        self.decide(
+            || time,
+            Decision::Timer,
+            |d| match d {
+                Decision::Timer(t) if t == time => Some(t),
+                _ => None,
+            },
+        );
+    }
+
+    /// Make a decision.
+    ///
+    /// In record mode, the decision made by `make` is appended to the trace.
+    /// In replay mode, the next decision in the trace is returned if it is accepted
+    /// by `accept`. Otherwise replay stops and the divergence is reported.
+    fn decide<T: Copy>(
+        &self,
+        make: impl FnOnce() -> T,
+        wrap: fn(T) -> Decision,
+        accept: impl FnOnce(Decision) -> Option<T>,
+    ) -> T {
+        let mut mode = self.inner.lock();
+        let (pos, recorded) = match &mut *mode {
+            Mode::Disabled => {
+                drop(mode);
+                return make();
+            }
+            Mode::Record(_) => {
+                // `make` may consume random numbers, so don't call it with the lock held.
+                drop(mode);
+                let value = make();
+                if let Mode::Record(trace) = &mut *self.inner.lock() {
+                    trace.decisions.push(wrap(value));
+                }
+                return value;
+            }
+            Mode::Replay { trace, pos } => {
+                let recorded = trace.decisions.get(*pos).copied();
+                if let Some(value) = recorded.and_then(accept) {
+                    *pos += 1;
+                    return value;
+                }
+                (*pos, recorded)
+            }
+        };
+        // diverged: stop replaying and make decisions as usual
+        *mode = Mode::Disabled;
+        drop(mode);
+        let value = make();
+        let time = crate::time::TimeHandle::try_current().map(|t| t.elapsed());
+        match recorded {
+            Some(recorded) => eprintln!(
+                "warning: replay diverged at decision #{pos} (time: {time:?}): \
+                 recorded `{recorded}`, but the code made `{}`",
+                wrap(value),
+            ),
+            None => eprintln!(
+                "warning: replay reached the end of trace at decision #{pos} (time: {time:?})"
+            ),
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::Runtime,
+        task::spawn,
+        time::{sleep, Duration},
+        Config,
+    };
+
+    #[test]
+    fn parse_trace() {
+        let s = "pick 1\npoll 60\ndelay 3000\nlink 1500000\nlink loss\ntimer 42\nfire 3\n";
+        let trace: Trace = s.parse().unwrap();
+        assert_eq!(trace.len(), 7);
+        assert_eq!(trace.to_string(), s);
+        assert!("pick x".parse::<Trace>().is_err());
+        assert!("jump 1".parse::<Trace>().is_err());
+    }
+
+    fn run(seed: u64, trace: Option<Trace>) -> (Vec<i32>, Duration, Option<Trace>) {
+        let runtime = Runtime::with_seed_and_config(seed, Config::default());
+        match trace {
+            Some(trace) => runtime.enable_replay(trace),
+            None => runtime.enable_record(),
+        }
+        let (seq, elapsed) = runtime.block_on(async {
+            let t0 = crate::time::TimeHandle::current().elapsed();
+            let (tx, rx) = std::sync::mpsc::channel();
+            let mut tasks = vec![];
+            for i in 0..3 {
+                let tx = tx.clone();
+                tasks.push(spawn(async move {
+                    for j in 0..3 {
+                        tx.send(i * 10 + j).unwrap();
+                        sleep(Duration::from_millis(1)).await;
+                    }
+                }));
+            }
+            drop(tx);
+            futures::future::join_all(tasks).await;
+            let elapsed = crate::time::TimeHandle::current().elapsed() - t0;
+            (rx.into_iter().collect::<Vec<_>>(), elapsed)
+        });
+        (seq, elapsed, runtime.take_trace())
+    }
+
+    #[test]
+    fn replay_with_different_seed() {
+        let (seq, elapsed, trace) = run(1, None);
+        let trace = trace.unwrap();
+        assert!(!trace.is_empty());
+        let (seq1, elapsed1, trace1) = run(2, Some(trace.clone()));
+        assert_eq!(seq, seq1);
+        assert_eq!(elapsed, elapsed1);
+        // the whole trace is replayed without divergence
+        assert_eq!(trace1, Some(trace));
+    }
+}
@@ -1,8 +1,19 @@
+use super::report::{Report, SeedResult};
 use super::{Config, Runtime};
+use crate::{plugin::Simulator, replay::Trace};
 use futures::StreamExt;
 use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, SystemTime};
 
+/// The environment variable set by the parent process in isolation mode.
+///
+/// It is the path of a file which the child process writes its result to.
+const ISOLATE_RESULT_ENV: &str = "MADSIM_TEST_ISOLATE_RESULT";
+
 /// Builds Madsim Runtime with custom configuration values.
 pub struct Builder {
     /// The random seed for test.
@@ -17,6 +28,30 @@ pub struct Builder {
     pub time_limit: Option<Duration>,
     /// Enable determinism check.
     pub check: bool,
+    /// Record all events in determinism check.
+    pub check_full: bool,
+    /// The file path to record nondeterministic decisions.
+    pub record: Option<PathBuf>,
+    /// The file path of a trace to replay.
+    pub replay: Option<PathBuf>,
+    /// Run each seed in a separate process.
+    pub isolate: bool,
+    /// Keep running after a seed fails, and print a summary at the end.
+    pub keep_going: bool,
+    /// The file path to write the summary as JSON.
+    pub report: Option<PathBuf>,
+    /// Stop at the simulated time to debug.
+    pub break_at: Option<Duration>,
+    /// Functions to register custom simulators to each runtime.
+    ///
+    /// Use [`add_simulator`](Builder::add_simulator) to add one.
+    pub simulators: Vec<fn(&Runtime)>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Builder {
@@ -24,123 +59,624 @@ impl Builder {
     ///
     /// - `MADSIM_TEST_SEED`: Set the random seed for test.
     ///
-    ///     By default, the seed is set to the seconds since the Unix epoch.
+    ///   By default, the seed is set to the seconds since the Unix epoch.
     ///
     /// - `MADSIM_TEST_NUM`: Set the number of tests.
     ///
-    ///     The seed will increase by 1 for each test.
+    ///   The seed will increase by 1 for each test.
     ///
-    ///     By default, the number is 1.
+    ///   By default, the number is 1.
     ///
     /// - `MADSIM_TEST_JOBS`: Set the number of jobs to run simultaneously.
     ///
-    ///     By default, the number of jobs is 1.
+    ///   By default, the number of jobs is 1.
     ///
-    /// - `MADSIM_TEST_CONFIG`: Set the config file path.
+    /// - `MADSIM_TEST_CONFIG`: Set the config file path. See [`Config`](crate::Config) for the format.
     ///
-    ///     By default, tests will use the default configuration.
+    ///   By default, tests will use the default configuration.
     ///
-    /// - `MADSIM_TEST_TIME_LIMIT`: Set the time limit for the test.
+    /// - `MADSIM_TEST_TIME_LIMIT`: Set the time limit for the test, e.g. `60s` or `500ms`.
     ///
-    ///     The test will panic if time limit exceeded in the simulation.
+    ///   A number without unit is in seconds.
+    ///   The test will panic if time limit exceeded in the simulation.
     ///
-    ///     By default, there is no time limit.
+    ///   By default, there is no time limit.
     ///
     /// - `MADSIM_TEST_CHECK_DETERMINISM`: Enable determinism check.
     ///
-    ///     The test will be run at least twice with the same seed.
-    ///     If any non-determinism detected, it will panic as soon as possible.
+    ///   The test will be run at least twice with the same seed.
+    ///   If any non-determinism detected, it will panic as soon as possible.
+    ///
+    ///   If the value is `full`, all events in the simulation are recorded,
+    ///   and the diverging event is reported along with the last common events.
+    ///
+    ///   By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_SCHEDULER`: Set the task scheduler.
+    ///
+    ///   The value can be `random`, `fifo` or `pct:depth=<d>,steps=<k>`.
+    ///   See [`SchedulerConfig`](crate::task::SchedulerConfig) for details.
+    ///
+    ///   By default, the scheduler in the config is used.
+    ///
+    /// - `MADSIM_TEST_RECORD`: Record nondeterministic decisions to the file.
+    ///
+    ///   If the number of tests is greater than 1, the seed will be appended
+    ///   to the file name, e.g. `trace.txt.42`.
+    ///
+    ///   By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_REPLAY`: Replay nondeterministic decisions from the file.
+    ///
+    ///   The trace file is generated by `MADSIM_TEST_RECORD`.
+    ///   If the running code diverges from the trace, a warning will be printed.
+    ///   It can not be used together with `MADSIM_TEST_RECORD`.
+    ///
+    ///   By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_STRICT`: Set the strict mode to detect escapes from the simulation.
+    ///
+    ///   The value can be `off`, `warn` or `panic`.
+    ///   See [`strict`](crate::strict) for details.
+    ///
+    ///   By default, the strict mode in the config is used.
     ///
-    ///     By default, it is disabled.
+    /// - `MADSIM_TEST_ISOLATE`: Run each seed in a separate process.
+    ///
+    ///   The test binary is re-executed for each seed, so an abort, stack overflow,
+    ///   `process::exit` or leaked global state in one seed doesn't affect the others.
+    ///   All failing seeds are reported with their exit causes.
+    ///   Only tests returning `()` or `Result<(), E>` can be run in this mode,
+    ///   see [`Builder::run_test`].
+    ///
+    ///   By default, it is disabled.
+    ///
+    /// - `MADSIM_TEST_KEEP_GOING`: Keep running after a seed fails.
+    ///
+    ///   At the end, a summary is printed with the number of passed and failed seeds,
+    ///   the failures grouped by panic message with the lowest reproducing seed,
+    ///   and statistics of the simulated time.
+    ///
+    ///   By default, it is disabled and the test panics on the first failure.
+    ///
+    /// - `MADSIM_TEST_REPORT`: Write the summary as JSON to the file.
+    ///
+    ///   This implies `MADSIM_TEST_KEEP_GOING`.
+    ///
+    ///   By default, it is disabled.
+    ///
+    /// - `MADSIM_BREAK_AT`: Stop when the simulated time passes the value, e.g. `12.345s`.
+    ///
+    ///   The state of all nodes and live tasks is printed to stderr.
+    ///   If a debugger is attached, `SIGTRAP` is raised to break into it.
+    ///   See also [`Handle::break_at`](crate::runtime::Handle::break_at).
+    ///
+    ///   By default, it is disabled.
     pub fn from_env() -> Self {
-        let seed: u64 = if let Ok(seed_str) = std::env::var("MADSIM_TEST_SEED") {
-            seed_str
-                .parse()
-                .expect("MADSIM_TEST_SEED should be an integer")
-        } else {
-            SystemTime::now()
+        Builder::new().with_env()
+    }
+
+    /// Create a new builder with default values.
+    ///
+    /// The seed is set to the seconds since the Unix epoch.
+    pub fn new() -> Self {
+        Builder {
+            seed: SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap()
-                .as_secs()
-        };
-        let jobs: u16 = if let Ok(jobs_str) = std::env::var("MADSIM_TEST_JOBS") {
-            jobs_str
+                .as_secs(),
+            count: 1,
+            jobs: 1,
+            config: Config::default(),
+            time_limit: None,
+            check: false,
+            check_full: false,
+            record: None,
+            replay: None,
+            isolate: false,
+            keep_going: false,
+            report: None,
+            break_at: None,
+            simulators: vec![],
+        }
+    }
+
+    /// Register a custom simulator to the runtime of each seed.
+    ///
+    /// Simulators can also be registered by the `simulators` argument of `#[madsim::test]`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{plugin::{self, Simulator}, rand::GlobalRng, runtime::Builder};
+    /// use madsim::{time::TimeHandle, Config};
+    ///
+    /// struct MySim;
+    ///
+    /// impl Simulator for MySim {
+    ///     fn new(_rand: &GlobalRng, _time: &TimeHandle, _config: &Config) -> Self {
+    ///         MySim
+    ///     }
+    /// }
+    ///
+    /// Builder::from_env().add_simulator::<MySim>().run(|| async {
+    ///     plugin::simulator::<MySim>();
+    /// });
+    /// ```
+    pub fn add_simulator<S: Simulator>(mut self) -> Self {
+        self.simulators.push(|rt| rt.add_simulator::<S>());
+        self
+    }
+
+    /// Override the values by environment variables.
+    ///
+    /// See [`from_env`](Builder::from_env) for the list of environment variables.
+    pub fn with_env(mut self) -> Self {
+        if let Ok(seed_str) = std::env::var("MADSIM_TEST_SEED") {
+            self.seed = seed_str
                 .parse()
-                .expect("MADSIM_TEST_JOBS should be an integer")
-        } else {
-            1
-        };
-        let config = if let Ok(config_path) = std::env::var("MADSIM_TEST_CONFIG") {
-            let content = std::fs::read_to_string(config_path).expect("failed to read config file");
-            content
+                .expect("MADSIM_TEST_SEED should be an integer");
+        }
+        if let Ok(jobs_str) = std::env::var("MADSIM_TEST_JOBS") {
+            self.jobs = jobs_str
+                .parse()
+                .expect("MADSIM_TEST_JOBS should be an integer");
+        }
+        if let Ok(config_path) = std::env::var("MADSIM_TEST_CONFIG") {
+            let content =
+                std::fs::read_to_string(&config_path).expect("failed to read config file");
+            self.config = content
                 .parse::<Config>()
-                .expect("failed to parse config file")
-        } else {
-            Config::default()
-        };
-        let mut count: u64 = if let Ok(num_str) = std::env::var("MADSIM_TEST_NUM") {
-            num_str
+                .unwrap_or_else(|e| panic!("invalid config file {config_path:?}: {e}"));
+        }
+        if let Ok(scheduler) = std::env::var("MADSIM_TEST_SCHEDULER") {
+            self.config.scheduler = scheduler
                 .parse()
-                .expect("MADSIM_TEST_NUM should be an integer")
-        } else {
-            1
-        };
-        let time_limit = std::env::var("MADSIM_TEST_TIME_LIMIT").ok().map(|num_str| {
-            Duration::from_secs_f64(
-                num_str
-                    .parse::<f64>()
-                    .expect("MADSIM_TEST_TIME_LIMIT should be an number"),
-            )
-        });
-        let check = std::env::var("MADSIM_TEST_CHECK_DETERMINISM").is_ok();
-        if check {
-            count = count.max(2);
+                .expect("MADSIM_TEST_SCHEDULER should be a valid scheduler");
         }
-        Builder {
-            seed,
-            count,
-            jobs,
-            config,
-            time_limit,
-            check,
+        if let Ok(mode) = std::env::var("MADSIM_TEST_STRICT") {
+            self.config.strict.mode = mode
+                .parse()
+                .expect("MADSIM_TEST_STRICT should be `off`, `warn` or `panic`");
+        }
+        if let Ok(num_str) = std::env::var("MADSIM_TEST_NUM") {
+            self.count = num_str
+                .parse()
+                .expect("MADSIM_TEST_NUM should be an integer");
+        }
+        if let Ok(limit) = std::env::var("MADSIM_TEST_TIME_LIMIT") {
+            self.time_limit = Some(
+                crate::sim::config::duration::parse_or_secs(&limit)
+                    .unwrap_or_else(|e| panic!("invalid MADSIM_TEST_TIME_LIMIT: {e}")),
+            );
+        }
+        if let Ok(check) = std::env::var("MADSIM_TEST_CHECK_DETERMINISM") {
+            self.check = true;
+            self.check_full = check == "full";
         }
+        if self.check {
+            self.count = self.count.max(2);
+        }
+        if let Ok(path) = std::env::var("MADSIM_TEST_RECORD") {
+            self.record = Some(path.into());
+        }
+        if let Ok(path) = std::env::var("MADSIM_TEST_REPLAY") {
+            self.replay = Some(path.into());
+        }
+        assert!(
+            self.record.is_none() || self.replay.is_none(),
+            "MADSIM_TEST_RECORD and MADSIM_TEST_REPLAY can not be set at the same time"
+        );
+        if let Ok(isolate) = std::env::var("MADSIM_TEST_ISOLATE") {
+            self.isolate = isolate != "0";
+        }
+        if let Ok(keep_going) = std::env::var("MADSIM_TEST_KEEP_GOING") {
+            self.keep_going = keep_going != "0";
+        }
+        if let Ok(path) = std::env::var("MADSIM_TEST_REPORT") {
+            self.report = Some(path.into());
+            self.keep_going = true;
+        }
+        if let Ok(time) = std::env::var("MADSIM_BREAK_AT") {
+            self.break_at = Some(
+                crate::sim::config::duration::parse_or_secs(&time)
+                    .unwrap_or_else(|e| panic!("invalid MADSIM_BREAK_AT: {e}")),
+            );
+        }
+        self
     }
 
     /// Run the future with configurations.
-    pub fn run<F>(self, f: fn() -> F) -> F::Output
+    ///
+    /// Isolation mode is ignored with a warning, because the output can not be
+    /// passed back from child processes. Use [`run_test`](Self::run_test) instead.
+    pub fn run<F>(mut self, f: fn() -> F) -> F::Output
     where
         F: Future + 'static,
         F::Output: Send,
     {
+        if self.isolate && std::env::var_os(ISOLATE_RESULT_ENV).is_none() {
+            log::warn!(
+                "isolation mode is ignored: it requires a test returning `()` or `Result<(), E>`"
+            );
+            self.isolate = false;
+        }
+        self.run_in_process(f)
+    }
+
+    /// Run a test returning `()` with configurations.
+    ///
+    /// If `isolate` is set, each seed is run in a child process.
+    pub fn run_test<F>(self, f: fn() -> F)
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        if self.isolate && std::env::var_os(ISOLATE_RESULT_ENV).is_none() {
+            self.run_isolated();
+        } else {
+            self.run_in_process(f);
+        }
+    }
+
+    fn run_in_process<F>(mut self, f: fn() -> F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: Send,
+    {
+        let result_path = std::env::var_os(ISOLATE_RESULT_ENV).map(PathBuf::from);
+        if result_path.is_some() {
+            // in a child process: run the only seed given by the parent
+            let seed = std::env::var("MADSIM_TEST_SEED").expect("MADSIM_TEST_SEED is not set");
+            self.seed = seed.parse().expect("MADSIM_TEST_SEED should be an integer");
+            self.count = 1;
+            self.keep_going = true;
+            self.report = None;
+        }
         if self.check {
-            return Runtime::check_determinism(self.seed, self.config, f);
+            let ret = Runtime::check_determinism_inner(
+                self.seed,
+                self.config,
+                self.simulators,
+                f,
+                self.check_full,
+            );
+            if let Some(path) = result_path {
+                let result = SeedResult {
+                    seed: self.seed,
+                    elapsed: None,
+                    error: None,
+                };
+                write_result(&path, &result);
+            }
+            return ret;
+        }
+        let (ret, results) = self.run_seeds(f);
+        if let Some(path) = result_path {
+            // pass the result to the parent
+            write_result(&path, &results[0]);
+            if results[0].error.is_some() {
+                std::process::exit(101);
+            }
+        } else if self.keep_going {
+            self.finish(&results);
         }
+        ret.unwrap()
+    }
+
+    /// Run all seeds in this process.
+    ///
+    /// Panics on the first failure unless `keep_going` is set.
+    fn run_seeds<F>(&self, f: fn() -> F) -> (Option<F::Output>, Vec<SeedResult>)
+    where
+        F: Future + 'static,
+        F::Output: Send,
+    {
+        assert!(
+            self.record.is_none() || self.replay.is_none(),
+            "can not record and replay at the same time"
+        );
+        let replay = (self.replay.as_ref())
+            .map(|path| Trace::load(path).expect("failed to load trace file"));
         let stream = futures::stream::iter(self.seed..self.seed + self.count)
             .map(|seed| {
                 let config = self.config.clone();
+                let replay = replay.clone();
+                let time_limit = self.time_limit;
+                let break_at = self.break_at;
+                let simulators = self.simulators.clone();
+                let record = self.record.as_ref().map(|path| match self.count {
+                    1 => path.clone(),
+                    _ => PathBuf::from(format!("{}.{seed}", path.display())),
+                });
                 async move {
                     let (tx, rx) = tokio::sync::oneshot::channel();
                     let handle = std::thread::spawn(move || {
                         let mut rt = Runtime::with_seed_and_config(seed, config);
-                        if let Some(limit) = self.time_limit {
+                        for add in simulators {
+                            add(&rt);
+                        }
+                        if let Some(limit) = time_limit {
                             rt.set_time_limit(limit);
                         }
-                        let ret = rt.block_on(f());
+                        if let Some(time) = break_at {
+                            rt.handle.task.break_at(time, None);
+                        }
+                        if let Some(trace) = replay {
+                            rt.enable_replay(trace);
+                        } else if record.is_some() {
+                            rt.enable_record();
+                        }
+                        let ret = catch_unwind(AssertUnwindSafe(|| rt.block_on(f())));
+                        let elapsed = rt.handle.time.elapsed();
+                        if let (Some(path), Some(trace)) = (&record, rt.take_trace()) {
+                            trace.save(path).expect("failed to save trace file");
+                            if ret.is_err() {
+                                eprintln!(
+                                    "note: decisions are recorded to {path:?}, \
+                                     run with `MADSIM_TEST_REPLAY={}` to replay",
+                                    path.display()
+                                );
+                            }
+                        }
                         tx.send(()).unwrap();
-                        ret
+                        (ret, elapsed)
                     });
                     let _ = rx.await;
-                    (seed, handle.join())
+                    match handle.join() {
+                        Ok((ret, elapsed)) => (seed, ret, Some(elapsed)),
+                        Err(e) => (seed, Err(e), None),
+                    }
                 }
             })
             .buffer_unordered(self.jobs as usize);
         let mut return_value = None;
-        for (seed, res) in futures::executor::block_on_stream(stream) {
-            match res {
-                Ok(ret) => return_value = Some(ret),
-                Err(e) => super::panic_with_info(seed, self.config.hash(), e),
+        let mut results = vec![];
+        for (seed, res, elapsed) in futures::executor::block_on_stream(stream) {
+            let error = match res {
+                Ok(ret) => {
+                    return_value = Some(ret);
+                    None
+                }
+                Err(e) if !self.keep_going => super::panic_with_info(seed, self.config.hash(), e),
+                Err(e) => Some(
+                    (crate::task::panic_message(&*e))
+                        .unwrap_or("<non-string panic payload>")
+                        .to_string(),
+                ),
+            };
+            results.push(SeedResult {
+                seed,
+                elapsed,
+                error,
+            });
+        }
+        (return_value, results)
+    }
+
+    /// Print the summary of all seeds and write the report.
+    ///
+    /// Panics if any seed failed.
+    fn finish(&self, results: &[SeedResult]) {
+        let report = Report::new(results);
+        eprintln!("{report}");
+        if let Some(path) = &self.report {
+            report.save(path).expect("failed to write the report file");
+        }
+        if let Some(seed) = report.first_failure() {
+            eprintln!(
+                "note: run with `MADSIM_TEST_SEED={seed}` environment variable to reproduce the first error"
+            );
+            eprintln!(
+                "      and make sure `MADSIM_CONFIG_HASH={:016X}`",
+                self.config.hash()
+            );
+            panic!("{} of {} seeds failed", report.failed, report.total);
+        }
+    }
+
+    /// Run each seed in a child process and report all failing seeds.
+    fn run_isolated(self) {
+        let stream = futures::stream::iter(self.seed..self.seed + self.count)
+            .map(|seed| {
+                let (mut cmd, result_path) = self.child_command(seed);
+                async move {
+                    let (tx, rx) = tokio::sync::oneshot::channel();
+                    std::thread::spawn(move || {
+                        let status = cmd.status().expect("failed to run the child process");
+                        let result = std::fs::read(&result_path).ok().map(|json| {
+                            let _ = std::fs::remove_file(&result_path);
+                            serde_json::from_slice::<SeedResult>(&json)
+                                .expect("failed to parse the result file")
+                        });
+                        let _ = tx.send((status, result));
+                    });
+                    (seed, rx.await.unwrap())
+                }
+            })
+            .buffer_unordered(self.jobs as usize);
+        let mut results = vec![];
+        let mut failures = vec![];
+        for (seed, (status, result)) in futures::executor::block_on_stream(stream) {
+            let found = result.is_some();
+            let mut result = result.unwrap_or(SeedResult {
+                seed,
+                elapsed: None,
+                error: None,
+            });
+            let cause = match (&result.error, status.success(), found) {
+                (Some(msg), _, _) => Some(format!("panicked with {msg:?}")),
+                (None, true, true) => None,
+                (None, true, false) => Some("the test was not run in the child process".into()),
+                (None, false, _) => Some(exit_cause(status)),
+            };
+            if let Some(cause) = cause {
+                result.error.get_or_insert_with(|| cause.clone());
+                failures.push((seed, cause));
             }
+            results.push(result);
+        }
+        if self.keep_going {
+            self.finish(&results);
+            return;
         }
-        return_value.unwrap()
+        if failures.is_empty() {
+            return;
+        }
+        failures.sort_unstable();
+        let mut msg = format!(
+            "{} of {} seeds failed in isolated processes:",
+            failures.len(),
+            self.count
+        );
+        for (seed, cause) in &failures {
+            msg += &format!("\n  seed {seed}: {cause}");
+        }
+        eprintln!(
+            "note: run with `MADSIM_TEST_SEED={}` environment variable to reproduce the first error",
+            failures[0].0
+        );
+        eprintln!(
+            "      and make sure `MADSIM_CONFIG_HASH={:016X}`",
+            self.config.hash()
+        );
+        panic!("{msg}");
+    }
+
+    /// Returns the command to run the given seed in a child process,
+    /// and the path of the file which the child writes its result to.
+    fn child_command(&self, seed: u64) -> (Command, PathBuf) {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let result_path = std::env::temp_dir().join(format!(
+            "madsim-isolate-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let exe = std::env::current_exe().expect("failed to get the current executable");
+        let mut cmd = Command::new(exe);
+        match std::thread::current().name() {
+            // the test harness runs each test in a thread named after the test
+            Some(name) if name != "main" => {
+                cmd.args([name, "--exact", "--include-ignored", "--nocapture"]);
+                cmd.args(["--test-threads=1", "--quiet"]);
+            }
+            _ => {
+                cmd.args(std::env::args_os().skip(1));
+            }
+        }
+        cmd.env_remove("MADSIM_TEST_ISOLATE")
+            .env("MADSIM_TEST_SEED", seed.to_string())
+            .env(ISOLATE_RESULT_ENV, &result_path);
+        if let Some(path) = &self.record {
+            let path = match self.count {
+                1 => path.clone(),
+                _ => PathBuf::from(format!("{}.{seed}", path.display())),
+            };
+            cmd.env("MADSIM_TEST_RECORD", path);
+        }
+        (cmd, result_path)
+    }
+}
+
+/// Write the result of a child process to the file.
+fn write_result(path: &Path, result: &SeedResult) {
+    let json = serde_json::to_string(result).unwrap();
+    std::fs::write(path, json).expect("failed to write the result file");
+}
+
+/// Describes why a child process failed.
+fn exit_cause(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        let name = match signal {
+            libc::SIGABRT => " (SIGABRT)",
+            libc::SIGSEGV => " (SIGSEGV)",
+            libc::SIGBUS => " (SIGBUS)",
+            libc::SIGILL => " (SIGILL)",
+            libc::SIGFPE => " (SIGFPE)",
+            libc::SIGKILL => " (SIGKILL)",
+            _ => "",
+        };
+        return format!("killed by signal {signal}{name}");
+    }
+    match status.code() {
+        Some(code) => format!("exited with code {code}"),
+        None => status.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Handle, time::sleep};
+
+    #[test]
+    fn isolate() {
+        let mut builder = Builder::from_env();
+        builder.seed = 1;
+        builder.count = 4;
+        builder.jobs = 2;
+        builder.isolate = true;
+        builder.keep_going = false;
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            builder.run_test(|| async {
+                match Handle::current().seed() {
+                    2 => std::process::abort(),
+                    3 => std::process::exit(3),
+                    4 => panic!("boom"),
+                    _ => {}
+                }
+            })
+        }));
+        if std::env::var_os(ISOLATE_RESULT_ENV).is_some() {
+            // this is a child process
+            return;
+        }
+        let msg = res.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            *msg,
+            "3 of 4 seeds failed in isolated processes:\n  \
+             seed 2: killed by signal 6 (SIGABRT)\n  \
+             seed 3: exited with code 3\n  \
+             seed 4: panicked with \"boom\""
+        );
+    }
+
+    #[test]
+    fn isolate_ignored_by_run() {
+        let mut builder = Builder::from_env();
+        builder.seed = 1;
+        builder.count = 2;
+        builder.isolate = true;
+        let seed = builder.run(|| async { Handle::current().seed() });
+        assert_eq!(seed, 2);
+    }
+
+    #[test]
+    fn keep_going() {
+        let path = std::env::temp_dir().join(format!("madsim-report-{}", std::process::id()));
+        let mut builder = Builder::from_env();
+        builder.seed = 1;
+        builder.count = 6;
+        builder.jobs = 3;
+        builder.check = false;
+        builder.isolate = false;
+        builder.keep_going = true;
+        builder.report = Some(path.clone());
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            builder.run(|| async {
+                let seed = Handle::current().seed();
+                sleep(Duration::from_secs(seed)).await;
+                assert!(![3, 6].contains(&seed), "seed is a multiple of 3");
+            })
+        }));
+        let msg = res.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(*msg, "2 of 6 seeds failed");
+        let json = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(report["passed"], 4);
+        assert_eq!(report["failures"][0]["message"], "seed is a multiple of 3");
+        assert_eq!(report["failures"][0]["seeds"], serde_json::json!([3, 6]));
+        assert!(report["time"]["max"].as_f64().unwrap() >= 5.0);
     }
 }
@@ -1,14 +1,14 @@
 //! Thread local runtime context
 use crate::{
     runtime::Handle,
-    task::{NodeId, TaskInfo},
+    task::{NodeId, TaskInfo, TaskMeta},
 };
 
 use std::{cell::RefCell, sync::Arc};
 
 thread_local! {
     static CONTEXT: RefCell<Option<Handle>> = RefCell::new(None);
-    static TASK: RefCell<Option<Arc<TaskInfo>>> = RefCell::new(None);
+    static TASK: RefCell<Option<Arc<TaskMeta>>> = RefCell::new(None);
 }
 
 pub(crate) fn current<T>(map: impl FnOnce(&Handle) -> T) -> T {
@@ -16,19 +16,28 @@ pub(crate) fn current<T>(map: impl FnOnce(&Handle) -> T) -> T {
 }
 
 pub(crate) fn try_current<T>(map: impl FnOnce(&Handle) -> T) -> Option<T> {
-    CONTEXT.with(move |ctx| ctx.borrow().as_ref().map(map))
+    // NOTE: this function may be called by libc overrides when the thread exits,
+    //       after the thread local has been destroyed.
+    CONTEXT
+        .try_with(move |ctx| ctx.borrow().as_ref().map(map))
+        .ok()
+        .flatten()
 }
 
 pub(crate) fn current_task() -> Arc<TaskInfo> {
-    TASK.with(|task| task.borrow().clone().expect(MSG))
+    TASK.with(|task| task.borrow().as_ref().expect(MSG).info.clone())
 }
 
 pub(crate) fn try_current_task() -> Option<Arc<TaskInfo>> {
+    TASK.with(|task| task.borrow().as_ref().map(|meta| meta.info.clone()))
+}
+
+pub(crate) fn try_current_task_meta() -> Option<Arc<TaskMeta>> {
     TASK.with(|task| task.borrow().clone())
 }
 
 pub(crate) fn current_node() -> NodeId {
-    TASK.with(|task| task.borrow().as_ref().expect(MSG).node)
+    TASK.with(|task| task.borrow().as_ref().expect(MSG).info.node)
 }
 
 /// Set this [`Handle`] as the current active [`Handle`].
@@ -51,14 +60,14 @@ impl Drop for EnterGuard {
     }
 }
 
-pub(crate) fn enter_task(new: Arc<TaskInfo>) -> TaskEnterGuard {
+pub(crate) fn enter_task(new: Arc<TaskMeta>) -> TaskEnterGuard {
     TASK.with(|ctx| {
         let old = ctx.borrow_mut().replace(new);
         TaskEnterGuard(old)
     })
 }
 
-pub(crate) struct TaskEnterGuard(Option<Arc<TaskInfo>>);
+pub(crate) struct TaskEnterGuard(Option<Arc<TaskMeta>>);
 
 impl Drop for TaskEnterGuard {
     fn drop(&mut self) {
@@ -6,14 +6,16 @@ use spin::Mutex;
 use std::{
     any::{Any, TypeId},
     collections::HashMap,
+    fmt,
     future::Future,
     net::IpAddr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 mod builder;
 pub(crate) mod context;
+mod report;
 
 pub use self::builder::Builder;
 
@@ -47,15 +49,24 @@ impl Runtime {
     }
 
     /// Create a new runtime instance with given seed and config.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the config is [invalid](Config::validate).
     pub fn with_seed_and_config(seed: u64, config: Config) -> Self {
+        if let Err(e) = config.validate() {
+            panic!("{e}");
+        }
+        crate::strict::reset_violation();
         let rand = rand::GlobalRng::new_with_seed(seed);
-        let task = task::Executor::new(rand.clone());
+        let task = task::Executor::new(rand.clone(), config.scheduler.build());
         let handle = Handle {
             rand: rand.clone(),
             time: task.time_handle().clone(),
             task: task.handle().clone(),
             sims: Default::default(),
             config,
+            allow_system_thread: Default::default(),
         };
         let rt = Runtime { rand, task, handle };
         rt.add_simulator::<fs::FsSim>();
@@ -66,12 +77,12 @@ impl Runtime {
     /// Register a simulator.
     pub fn add_simulator<S: plugin::Simulator>(&self) {
         let mut sims = self.handle.sims.lock();
-        let sim = Arc::new(S::new1(
-            &self.handle.rand,
-            &self.handle.time,
-            &self.handle.task.get_node(NodeId::zero()).unwrap(),
-            &self.handle.config,
-        ));
+        let sim = Arc::new(S::with_context(&plugin::Context {
+            rand: &self.handle.rand,
+            time: &self.handle.time,
+            task: &self.handle.task.get_node(NodeId::zero()).unwrap(),
+            config: &self.handle.config,
+        }));
         // create node for supervisor
         sim.create_node(NodeId::zero());
         sims.insert(TypeId::of::<S>(), sim);
@@ -118,11 +129,125 @@ impl Runtime {
     ///
     /// Runtime::new().block_on(pending::<()>());
     /// ```
+    #[track_caller]
     pub fn block_on<F: Future>(&self, future: F) -> F::Output {
         let _guard = crate::context::enter(self.handle.clone());
         self.task.block_on(future)
     }
 
+    /// Run the simulation until the time reaches `deadline`.
+    ///
+    /// All runnable tasks are run and all timers up to `deadline` are fired.
+    /// Then the time is set to `deadline`, and the world is frozen until the
+    /// runtime is driven again. Unlike [`block_on`], this method returns
+    /// instead of panicking when there is nothing to do.
+    ///
+    /// Together with [`step`] and [`run_until_idle`], this allows a test
+    /// harness to stop the simulation at a given time, inspect the state,
+    /// inject faults and continue.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{runtime::Runtime, time::{sleep, Duration}};
+    /// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    ///
+    /// let rt = Runtime::new();
+    /// let node = rt.create_node().build();
+    /// let count = Arc::new(AtomicUsize::new(0));
+    /// let count1 = count.clone();
+    /// node.spawn(async move {
+    ///     loop {
+    ///         sleep(Duration::from_secs(1)).await;
+    ///         count1.fetch_add(1, Ordering::SeqCst);
+    ///     }
+    /// });
+    ///
+    /// let start = rt.now();
+    /// rt.run_until(start + Duration::from_millis(3500));
+    /// assert_eq!(count.load(Ordering::SeqCst), 3);
+    ///
+    /// // inject a fault and continue
+    /// rt.handle().kill(node.id());
+    /// rt.run_until(start + Duration::from_secs(10));
+    /// assert_eq!(count.load(Ordering::SeqCst), 3);
+    /// ```
+    ///
+    /// [`block_on`]: Runtime::block_on
+    /// [`step`]: Runtime::step
+    /// [`run_until_idle`]: Runtime::run_until_idle
+    pub fn run_until(&self, deadline: Instant) {
+        let _guard = crate::context::enter(self.handle.clone());
+        self.task.run_until(deadline);
+    }
+
+    /// Run all runnable tasks until none is left, then fire the next timer event.
+    ///
+    /// Returns `false` if there was neither a runnable task nor a timer event.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{runtime::Runtime, time::{sleep, Duration}};
+    ///
+    /// let rt = Runtime::new();
+    /// let node = rt.create_node().build();
+    /// node.spawn(async { sleep(Duration::from_secs(1)).await });
+    ///
+    /// let start = rt.now();
+    /// assert!(rt.step());
+    /// assert!(rt.now() >= start + Duration::from_secs(1));
+    /// assert!(rt.step());
+    /// assert!(!rt.step());
+    /// ```
+    pub fn step(&self) -> bool {
+        let _guard = crate::context::enter(self.handle.clone());
+        self.task.step()
+    }
+
+    /// Run all runnable tasks until none is left, without advancing to the next timer.
+    pub fn run_until_idle(&self) {
+        let _guard = crate::context::enter(self.handle.clone());
+        self.task.run_all_ready();
+    }
+
+    /// Returns the current simulated time.
+    pub fn now(&self) -> Instant {
+        self.handle.time.now_instant()
+    }
+
+    /// Returns the deadline of the next timer event, or `None` if there is no timer.
+    pub fn next_timer_deadline(&self) -> Option<Instant> {
+        self.task.next_timer_deadline()
+    }
+
+    /// Returns the number of runnable tasks on each node.
+    ///
+    /// The supervisor node [`NodeId`] `0` is included. Tasks of paused nodes
+    /// are not counted until the node is resumed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::Runtime;
+    ///
+    /// let rt = Runtime::new();
+    /// let node = rt.create_node().build();
+    /// node.spawn(async {});
+    /// node.spawn(async {});
+    /// assert_eq!(rt.runnable_tasks()[&node.id()], 2);
+    ///
+    /// rt.handle().pause(node.id());
+    /// assert_eq!(rt.runnable_tasks()[&node.id()], 0);
+    ///
+    /// rt.handle().resume(node.id());
+    /// rt.run_until_idle();
+    /// assert_eq!(rt.runnable_tasks()[&node.id()], 0);
+    /// ```
+    pub fn runnable_tasks(&self) -> HashMap<NodeId, usize> {
+        self.task.runnable_tasks()
+    }
+
     /// Set a time limit of the execution.
     ///
     /// The runtime will panic when time limit exceeded.
@@ -143,6 +268,76 @@ impl Runtime {
         self.task.set_time_limit(limit);
     }
 
+    /// Allow or forbid spawning system threads in the simulation.
+    ///
+    /// A system thread (e.g. spawned by [`std::thread::spawn`]) escapes from the
+    /// simulation and may break determinism. By default, spawning one inside the
+    /// runtime prints a warning, and fails in [strict mode](crate::strict) unless
+    /// `pthread_create` is allowed. Set `true` to allow it silently, or `false`
+    /// to make it always fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::Runtime;
+    ///
+    /// let rt = Runtime::new();
+    /// rt.block_on(async {
+    ///     std::thread::spawn(|| {}).join().unwrap();
+    /// });
+    /// rt.set_allow_system_thread(false);
+    /// rt.block_on(async {
+    ///     assert!(std::thread::Builder::new().spawn(|| {}).is_err());
+    /// });
+    /// ```
+    pub fn set_allow_system_thread(&self, allowed: bool) {
+        *self.handle.allow_system_thread.lock() = Some(allowed);
+    }
+
+    /// Set the task scheduler.
+    ///
+    /// By default, the scheduler is built from [`Config::scheduler`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{runtime::Runtime, task::FifoScheduler};
+    ///
+    /// let rt = Runtime::new();
+    /// rt.set_scheduler(FifoScheduler::default());
+    /// rt.block_on(async {});
+    /// ```
+    pub fn set_scheduler(&self, scheduler: impl task::Scheduler) {
+        self.task.set_scheduler(Box::new(scheduler));
+    }
+
+    /// Record all nondeterministic decisions into a [`Trace`].
+    ///
+    /// The trace can be taken by [`take_trace`](Runtime::take_trace)
+    /// and replayed by [`enable_replay`](Runtime::enable_replay).
+    ///
+    /// [`Trace`]: crate::replay::Trace
+    pub fn enable_record(&self) {
+        self.rand.recorder().enable_record();
+    }
+
+    /// Replay nondeterministic decisions from a [`Trace`].
+    ///
+    /// If the running code diverges from the trace, a warning is printed and
+    /// the rest of the simulation makes decisions as usual.
+    ///
+    /// [`Trace`]: crate::replay::Trace
+    pub fn enable_replay(&self, trace: replay::Trace) {
+        self.rand.recorder().enable_replay(trace);
+    }
+
+    /// Take the recorded or replayed trace.
+    ///
+    /// Returns `None` if neither record nor replay is enabled, or replay has diverged.
+    pub fn take_trace(&self) -> Option<replay::Trace> {
+        self.rand.recorder().take_trace()
+    }
+
     /// Check determinism of the future.
     ///
     /// # Example
@@ -162,15 +357,59 @@ impl Runtime {
     /// });
     /// ```
     pub fn check_determinism<F>(seed: u64, config: Config, f: fn() -> F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: Send,
+    {
+        Self::check_determinism_inner(seed, config, vec![], f, false)
+    }
+
+    /// Check determinism of the future, recording every event in the simulation.
+    ///
+    /// This is slower than [`check_determinism`](Runtime::check_determinism),
+    /// but on failure it reports the diverging event along with the last common
+    /// events, including task polls, timer events, network packets and the call
+    /// site of random numbers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{Config, runtime::Runtime, time::{Duration, sleep}};
+    ///
+    /// Runtime::check_determinism_full(0, Config::default(), || async {
+    ///     let secs = madsim::rand::random::<u8>();
+    ///     sleep(Duration::from_secs(secs as u64)).await;
+    /// });
+    /// ```
+    pub fn check_determinism_full<F>(seed: u64, config: Config, f: fn() -> F) -> F::Output
+    where
+        F: Future + 'static,
+        F::Output: Send,
+    {
+        Self::check_determinism_inner(seed, config, vec![], f, true)
+    }
+
+    /// Check determinism of the future, with custom simulators added by `sims`.
+    fn check_determinism_inner<F>(
+        seed: u64,
+        config: Config,
+        sims: Vec<fn(&Runtime)>,
+        f: fn() -> F,
+        full: bool,
+    ) -> F::Output
     where
         F: Future + 'static,
         F::Output: Send,
     {
         let hash = config.hash();
         let config0 = config.clone();
+        let sims0 = sims.clone();
         let log = std::thread::spawn(move || {
             let rt = Runtime::with_seed_and_config(seed, config0);
-            rt.rand.enable_log();
+            for add in sims0 {
+                add(&rt);
+            }
+            rt.rand.enable_log(full);
             rt.block_on(f());
             rt.rand.take_log().unwrap()
         })
@@ -180,8 +419,13 @@ impl Runtime {
 
         std::thread::spawn(move || {
             let rt = Runtime::with_seed_and_config(seed, config);
+            for add in sims {
+                add(&rt);
+            }
             rt.rand.enable_check(log);
-            rt.block_on(f())
+            let ret = rt.block_on(f());
+            rt.rand.finish_check();
+            ret
         })
         .join()
         .map_err(|e| panic_with_info(seed, hash, e))
@@ -206,6 +450,8 @@ pub struct Handle {
     pub(crate) task: task::TaskHandle,
     pub(crate) sims: Arc<Mutex<HashMap<TypeId, Arc<dyn plugin::Simulator>>>>,
     pub(crate) config: Config,
+    /// Whether spawning system threads is allowed. `None` means warning about it.
+    pub(crate) allow_system_thread: Arc<Mutex<Option<bool>>>,
 }
 
 impl Handle {
@@ -236,35 +482,143 @@ impl Handle {
 
     /// Kill a node.
     ///
-    /// - All tasks spawned on this node will be killed immediately.
-    /// - All data that has not been flushed to the disk will be lost.
+    /// This is the same as [`kill_process`](Handle::kill_process).
+    /// Use [`power_off`](Handle::power_off) to also lose the data not synced to the disk.
     pub fn kill(&self, id: NodeId) {
-        self.task.kill(id);
-        let sims = self.sims.lock();
-        let values = sims.values();
-        for sim in values {
-            sim.reset_node(id);
-        }
+        self.kill_process(id);
     }
 
     /// Restart a node。
+    ///
+    /// This is the same as [`restart_process`](Handle::restart_process).
+    /// Use [`reboot`](Handle::reboot) to also lose the data not synced to the disk.
     pub fn restart(&self, id: NodeId) {
-        self.task.restart(id);
+        self.restart_process(id);
+    }
+
+    /// Kill the process of a node, as if it crashed.
+    ///
+    /// All tasks spawned on this node will be killed immediately, and all
+    /// connections will be closed. Since the machine keeps running, data
+    /// written to files survives even if it has not been synced to the disk.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{fs, runtime::Runtime};
+    ///
+    /// let rt = Runtime::new();
+    /// let node = rt.create_node().build();
+    /// let write = || async {
+    ///     let file = fs::File::create("file").await.unwrap();
+    ///     file.write_all_at(b"hello", 0).await.unwrap();
+    /// };
+    /// let exists = || async { fs::metadata("file").await.is_ok() };
+    ///
+    /// rt.block_on(node.spawn(write())).unwrap();
+    /// rt.handle().kill_process(node.id());
+    /// let node = rt.handle().get_node(node.id()).unwrap();
+    /// assert!(rt.block_on(node.spawn(exists())).unwrap());
+    ///
+    /// // unsynced files are lost on power loss
+    /// rt.handle().power_off(node.id());
+    /// let node = rt.handle().get_node(node.id()).unwrap();
+    /// assert!(!rt.block_on(node.spawn(exists())).unwrap());
+    /// ```
+    pub fn kill_process(&self, id: NodeId) {
+        self.reset(id, plugin::ResetKind::Crash, false);
+    }
+
+    /// Kill the process of a node as if it crashed, and restart the initial task.
+    ///
+    /// See [`kill_process`](Handle::kill_process) for details.
+    pub fn restart_process(&self, id: NodeId) {
+        self.reset(id, plugin::ResetKind::Crash, true);
+    }
+
+    /// Power off a node.
+    ///
+    /// All tasks spawned on this node will be killed immediately, and all
+    /// connections will be closed. All data that has not been synced to the
+    /// disk will be lost.
+    pub fn power_off(&self, id: NodeId) {
+        self.reset(id, plugin::ResetKind::PowerLoss, false);
+    }
+
+    /// Power off a node and start it again with the initial task.
+    ///
+    /// See [`power_off`](Handle::power_off) for details.
+    pub fn reboot(&self, id: NodeId) {
+        self.reset(id, plugin::ResetKind::PowerLoss, true);
+    }
+
+    /// Kill all tasks of a node, optionally restart it, and reset it in all simulators.
+    fn reset(&self, id: NodeId, kind: plugin::ResetKind, restart: bool) {
+        if restart {
+            self.task.restart(id);
+        } else {
+            self.task.kill(id);
+        }
         let sims = self.sims.lock();
         let values = sims.values();
         for sim in values {
-            sim.reset_node(id);
+            sim.on_kill(id, kind);
+            if restart {
+                sim.on_restart(id);
+            }
         }
     }
 
     /// Pause the execution of a node.
     pub fn pause(&self, id: NodeId) {
         self.task.pause(id);
+        let sims = self.sims.lock();
+        let values = sims.values();
+        for sim in values {
+            sim.on_pause(id);
+        }
     }
 
     /// Resume the execution of a node.
     pub fn resume(&self, id: NodeId) {
         self.task.resume(id);
+        let sims = self.sims.lock();
+        let values = sims.values();
+        for sim in values {
+            sim.on_resume(id);
+        }
+    }
+
+    /// Call `f` once the simulated time passes `time` since the start of simulation.
+    ///
+    /// The state of all nodes and live tasks is printed to stderr before `f` is called.
+    /// This is useful to get into a debugger at the interesting moment of a failing seed,
+    /// e.g. by setting a breakpoint in `f`.
+    ///
+    /// Breakpoints can also be set by the `MADSIM_BREAK_AT` environment variable.
+    /// See [`Builder::from_env`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{runtime::{Handle, Runtime}, time::{sleep, Duration}};
+    /// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+    ///
+    /// let rt = Runtime::new();
+    /// let hit = Arc::new(AtomicBool::new(false));
+    /// let hit1 = hit.clone();
+    /// rt.handle().break_at(Duration::from_secs(1), move || {
+    ///     hit1.store(true, Ordering::SeqCst);
+    /// });
+    /// rt.block_on(async move {
+    ///     sleep(Duration::from_millis(999)).await;
+    ///     assert!(!hit.load(Ordering::SeqCst));
+    ///     sleep(Duration::from_millis(1)).await;
+    ///     assert!(hit.load(Ordering::SeqCst));
+    /// });
+    /// ```
+    pub fn break_at(&self, time: Duration, f: impl FnOnce() + Send + 'static) {
+        self.task.break_at(time, Some(Box::new(f)));
     }
 
     /// Create a node which will be bound to the specified address.
@@ -272,9 +626,136 @@ impl Handle {
         NodeBuilder::new(self)
     }
 
+    /// Remove a node.
+    ///
+    /// All tasks of the node are killed, its sockets are closed, its IP address
+    /// is released and its file system is dropped. The node ID will not be reused.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::{NodeStatus, Runtime};
+    ///
+    /// let rt = Runtime::new();
+    /// let node = rt.create_node().ip([10, 0, 0, 1].into()).build();
+    /// rt.handle().remove_node(node.id());
+    /// assert_eq!(node.status(), NodeStatus::Removed);
+    /// assert!(rt.handle().get_node(node.id()).is_none());
+    ///
+    /// // the IP address can be used by another node
+    /// rt.create_node().ip([10, 0, 0, 1].into()).build();
+    /// ```
+    pub fn remove_node(&self, id: NodeId) {
+        self.task.remove_node(id);
+        let sims = self.sims.lock();
+        let values = sims.values();
+        for sim in values {
+            sim.on_remove_node(id);
+        }
+    }
+
+    /// Replace a node with a fresh machine, as if a cloud VM is replaced.
+    ///
+    /// Returns a builder of the new node, which has the same name, IP address,
+    /// initial task and other settings as the old node by default. They can be
+    /// changed before [`build`](NodeBuilder::build). When the new node is built,
+    /// the old node is removed as by [`remove_node`](Handle::remove_node), and the
+    /// new node starts with an empty file system and a new node ID.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::Runtime;
+    ///
+    /// let rt = Runtime::new();
+    /// let old = rt.create_node().name("server").ip([10, 0, 0, 1].into()).build();
+    /// let new = rt.handle().replace_node(old.id()).build();
+    /// assert_ne!(new.id(), old.id());
+    /// assert_eq!(new.name(), "server");
+    /// assert_eq!(new.ip(), Some([10, 0, 0, 1].into()));
+    /// ```
+    pub fn replace_node(&self, id: NodeId) -> NodeBuilder<'_> {
+        let node = self.get_node(id).expect("node not found");
+        NodeBuilder {
+            handle: self,
+            name: Some(node.name().into()),
+            ip: node.ip(),
+            cores: Some(node.task.cores()),
+            max_blocking_threads: Some(node.task.max_blocking_threads()),
+            on_panic: node.task.on_panic(),
+            init: self.task.get_init(id),
+            hostnames: self.net().hostnames(id),
+            zone: node.zone(),
+            region: node.region(),
+            replace: Some(id),
+        }
+    }
+
     /// Return a handle of the specified node.
     pub fn get_node(&self, id: NodeId) -> Option<NodeHandle> {
-        self.task.get_node(id).map(|task| NodeHandle { task })
+        let task = self.task.get_node(id)?;
+        Some(NodeHandle {
+            task,
+            net: self.net(),
+        })
+    }
+
+    /// Returns handles of all nodes in the order of creation.
+    ///
+    /// The supervisor node (the future in [`Runtime::block_on`]) is not included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::{Handle, NodeStatus, Runtime};
+    ///
+    /// let rt = Runtime::new();
+    /// rt.block_on(async {
+    ///     let handle = Handle::current();
+    ///     for i in 0..3 {
+    ///         handle.create_node().name(format!("server-{i}")).build();
+    ///     }
+    ///     // kill all servers except the first one
+    ///     for node in handle.nodes().iter().skip(1) {
+    ///         handle.kill(node.id());
+    ///     }
+    ///     let status: Vec<_> = handle.nodes().iter().map(|node| node.status()).collect();
+    ///     assert_eq!(status, [NodeStatus::Running, NodeStatus::Killed, NodeStatus::Killed]);
+    /// });
+    /// ```
+    pub fn nodes(&self) -> Vec<NodeHandle> {
+        (self.task.node_ids().into_iter())
+            .filter_map(|id| self.get_node(id))
+            .collect()
+    }
+
+    /// Returns the node with the given name.
+    ///
+    /// If there are multiple nodes with the same name, the one created first is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::runtime::Runtime;
+    ///
+    /// let rt = Runtime::new();
+    /// let node = rt.create_node().name("server").ip([10, 0, 0, 1].into()).build();
+    /// let found = rt.handle().node_by_name("server").unwrap();
+    /// assert_eq!(found.id(), node.id());
+    /// assert_eq!(found.ip(), Some([10, 0, 0, 1].into()));
+    /// assert!(rt.handle().node_by_name("client").is_none());
+    /// ```
+    pub fn node_by_name(&self, name: &str) -> Option<NodeHandle> {
+        self.get_node(self.task.node_by_name(name)?)
+    }
+
+    /// Returns the network simulator.
+    fn net(&self) -> Arc<net::NetSim> {
+        let sims = self.sims.lock();
+        (sims[&TypeId::of::<net::NetSim>()].clone())
+            .downcast_arc()
+            .ok()
+            .unwrap()
     }
 }
 
@@ -284,7 +765,14 @@ pub struct NodeBuilder<'a> {
     name: Option<String>,
     ip: Option<IpAddr>,
     cores: Option<usize>,
-    init: Option<Arc<dyn Fn(&task::TaskNodeHandle)>>,
+    max_blocking_threads: Option<usize>,
+    on_panic: PanicPolicy,
+    init: Option<task::InitFn>,
+    hostnames: Vec<String>,
+    zone: Option<String>,
+    region: Option<String>,
+    /// The node to be replaced.
+    replace: Option<NodeId>,
 }
 
 impl<'a> NodeBuilder<'a> {
@@ -294,18 +782,46 @@ impl<'a> NodeBuilder<'a> {
             name: None,
             ip: None,
             cores: None,
+            max_blocking_threads: None,
+            on_panic: PanicPolicy::default(),
             init: None,
+            hostnames: vec![],
+            zone: None,
+            region: None,
+            replace: None,
         }
     }
 
     /// Names the node.
     ///
     /// The default name is node ID.
+    /// The name is also registered to the simulated [`Dns`](crate::net::Dns) as a hostname.
     pub fn name(mut self, name: impl Into<String>) -> Self {
         self.name = Some(name.into());
         self
     }
 
+    /// Add a hostname of the node to the simulated [`Dns`](crate::net::Dns).
+    ///
+    /// The hostname resolves to the IP address of the node.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use madsim::{net, runtime::Runtime};
+    ///
+    /// let rt = Runtime::new();
+    /// rt.create_node().hostname("db.local").ip([10, 0, 0, 1].into()).build();
+    /// rt.block_on(async {
+    ///     let mut addrs = net::lookup_host("db.local:5432").await.unwrap();
+    ///     assert_eq!(addrs.next(), Some("10.0.0.1:5432".parse().unwrap()));
+    /// });
+    /// ```
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostnames.push(hostname.into());
+        self
+    }
+
     /// Set the initial task for the node.
     ///
     /// This task will be automatically respawned after crash.
@@ -325,6 +841,24 @@ impl<'a> NodeBuilder<'a> {
         self
     }
 
+    /// Set the availability zone of the node.
+    ///
+    /// The latency and packet loss between zones can be set by
+    /// [`net::Config::links`](crate::net::Config::links).
+    pub fn zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    /// Set the region of the node.
+    ///
+    /// The latency and packet loss between regions can be set by
+    /// [`net::Config::links`](crate::net::Config::links).
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
     /// Set the number of CPU cores of the node.
     ///
     /// This will be the return value of [`std::thread::available_parallelism`].
@@ -334,30 +868,110 @@ impl<'a> NodeBuilder<'a> {
         self
     }
 
+    /// Set the number of threads in the blocking pool of the node.
+    ///
+    /// This limits how many [`spawn_blocking`](crate::task::spawn_blocking) calls
+    /// run concurrently. The default value is 512.
+    pub fn max_blocking_threads(mut self, threads: usize) -> Self {
+        assert_ne!(threads, 0, "max_blocking_threads must be greater than 0");
+        self.max_blocking_threads = Some(threads);
+        self
+    }
+
+    /// Set what to do when a task on the node panics.
+    ///
+    /// By default, the panic is propagated to the caller of [`Runtime::block_on`].
+    pub fn on_panic(mut self, policy: PanicPolicy) -> Self {
+        self.on_panic = policy;
+        self
+    }
+
     /// Build a node.
     pub fn build(self) -> NodeHandle {
-        let task = self
-            .handle
-            .task
-            .create_node(self.name, self.init, self.cores);
+        if let Some(id) = self.replace {
+            self.handle.remove_node(id);
+        }
+        let task = (self.handle.task).create_node(
+            self.name.clone(),
+            self.init,
+            self.cores,
+            self.max_blocking_threads,
+            self.on_panic,
+        );
         let sims = self.handle.sims.lock();
         let values = sims.values();
         for sim in values {
             sim.create_node(task.id());
-            if let Some(ip) = self.ip {
-                if let Some(net) = sim.downcast_ref::<net::NetSim>() {
+            if let Some(net) = sim.downcast_ref::<net::NetSim>() {
+                if let Some(ip) = self.ip {
                     net.set_ip(task.id(), ip)
                 }
+                net.set_location(task.id(), self.zone.clone(), self.region.clone());
             }
         }
-        NodeHandle { task }
+        drop(sims);
+        let net = self.handle.net();
+        for hostname in self.name.iter().chain(&self.hostnames) {
+            net.add_hostname(task.id(), hostname);
+        }
+        NodeHandle { task, net }
+    }
+}
+
+/// What to do when a task on a node panics.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Propagate the panic to the caller of [`Runtime::block_on`],
+    /// which usually fails the test.
+    #[default]
+    Propagate,
+    /// Kill the node, as if the process crashed.
+    ///
+    /// See [`Handle::kill_process`].
+    ///
+    /// Awaiting the panicked task returns a [`JoinError`](crate::task::JoinError)
+    /// which contains the panic payload.
+    Kill,
+    /// Kill and restart the node, as if the process crashed and was restarted
+    /// by a supervisor.
+    ///
+    /// See [`Handle::restart_process`].
+    Restart,
+}
+
+/// The status of a node.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeStatus {
+    /// The node is running.
+    #[default]
+    Running,
+    /// The node is paused by [`Handle::pause`].
+    Paused,
+    /// The node is killed by [`Handle::kill_process`] or [`Handle::power_off`],
+    /// and has not been restarted.
+    Killed,
+    /// The node is removed by [`Handle::remove_node`].
+    Removed,
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "running"),
+            Self::Paused => write!(f, "paused"),
+            Self::Killed => write!(f, "killed"),
+            Self::Removed => write!(f, "removed"),
+        }
     }
 }
 
 /// Handle to a node.
 #[derive(Clone)]
 pub struct NodeHandle {
-    task: task::TaskNodeHandle,
+    pub(crate) task: task::TaskNodeHandle,
+    net: Arc<net::NetSim>,
 }
 
 impl NodeHandle {
@@ -366,7 +980,35 @@ impl NodeHandle {
         self.task.id()
     }
 
+    /// Returns the name of the node.
+    ///
+    /// The name is set by [`NodeBuilder::name`], or `node-<id>` by default.
+    pub fn name(&self) -> &str {
+        self.task.name()
+    }
+
+    /// Returns the current status of the node.
+    pub fn status(&self) -> NodeStatus {
+        self.task.status()
+    }
+
+    /// Returns the IP address of the node.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.net.get_ip(self.id())
+    }
+
+    /// Returns the availability zone of the node.
+    pub fn zone(&self) -> Option<String> {
+        self.net.get_location(self.id()).0
+    }
+
+    /// Returns the region of the node.
+    pub fn region(&self) -> Option<String> {
+        self.net.get_location(self.id()).1
+    }
+
     /// Spawn a future onto the runtime.
+    #[track_caller]
     pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
     where
         F: Future + Send + 'static,
@@ -409,8 +1051,11 @@ pub fn init_logger() {
                 write!(buf, "{:.9}s", time.elapsed().as_secs_f64())?;
             }
             write!(buf, " {:>5}", level_style.value(record.level()))?;
-            if let Some(task) = crate::context::try_current_task() {
-                write!(buf, " {}", task.name)?;
+            if let Some(task) = crate::context::try_current_task_meta() {
+                write!(buf, " {}", task.info.name)?;
+                if let Some(name) = &task.name {
+                    write!(buf, "/{}", name)?;
+                }
             }
             write!(buf, " {:>10}", style.value(record.target()))?;
             writeln!(buf, "{} {}", style.value(']'), record.args())
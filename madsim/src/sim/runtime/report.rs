@@ -0,0 +1,174 @@
+//! Summary of a seed sweep.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, io, path::Path, time::Duration};
+
+/// The maximum number of seeds shown for each failure in the summary.
+const MAX_SEEDS_SHOWN: usize = 10;
+
+/// The result of running a seed.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct SeedResult {
+    pub seed: u64,
+    /// The simulated time when the run ends, if known.
+    pub elapsed: Option<Duration>,
+    /// The panic message or exit cause if the seed failed.
+    pub error: Option<String>,
+}
+
+/// Summary of a seed sweep.
+#[derive(Debug, Serialize)]
+pub(super) struct Report {
+    /// The number of seeds.
+    pub total: usize,
+    /// The number of passed seeds.
+    pub passed: usize,
+    /// The number of failed seeds.
+    pub failed: usize,
+    /// Failures grouped by message, ordered by the lowest seed.
+    pub failures: Vec<Failure>,
+    /// Statistics of the simulated time of passed seeds, in seconds.
+    pub time: Option<TimeStats>,
+}
+
+/// Seeds failed with the same message.
+#[derive(Debug, Serialize)]
+pub(super) struct Failure {
+    pub message: String,
+    /// The lowest seed that reproduces the failure.
+    pub seed: u64,
+    /// All seeds failed with the message, in ascending order.
+    pub seeds: Vec<u64>,
+}
+
+/// Statistics of the simulated time, in seconds.
+#[derive(Debug, Serialize)]
+pub(super) struct TimeStats {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl Report {
+    pub fn new(results: &[SeedResult]) -> Self {
+        let mut groups = HashMap::<&str, Vec<u64>>::new();
+        let mut times = vec![];
+        for result in results {
+            match &result.error {
+                Some(msg) => groups.entry(msg).or_default().push(result.seed),
+                None => times.extend(result.elapsed.map(|t| t.as_secs_f64())),
+            }
+        }
+        let mut failures = (groups.into_iter())
+            .map(|(message, mut seeds)| {
+                seeds.sort_unstable();
+                Failure {
+                    message: message.into(),
+                    seed: seeds[0],
+                    seeds,
+                }
+            })
+            .collect::<Vec<_>>();
+        failures.sort_unstable_by_key(|f| f.seed);
+        let time = (!times.is_empty()).then(|| TimeStats {
+            min: times.iter().copied().fold(f64::INFINITY, f64::min),
+            mean: times.iter().sum::<f64>() / times.len() as f64,
+            max: times.iter().copied().fold(0.0, f64::max),
+        });
+        let failed = failures.iter().map(|f| f.seeds.len()).sum();
+        Report {
+            total: results.len(),
+            passed: results.len() - failed,
+            failed,
+            failures,
+            time,
+        }
+    }
+
+    /// Save the report as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns the lowest failing seed.
+    pub fn first_failure(&self) -> Option<u64> {
+        self.failures.iter().map(|f| f.seed).min()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "seed sweep: {} passed, {} failed, {} total",
+            self.passed, self.failed, self.total
+        )?;
+        if !self.failures.is_empty() {
+            write!(f, "\nfailures:")?;
+        }
+        for failure in &self.failures {
+            write!(
+                f,
+                "\n  {} seed(s), lowest seed {}: {:?}\n    seeds: ",
+                failure.seeds.len(),
+                failure.seed,
+                failure.message
+            )?;
+            for (i, seed) in failure.seeds.iter().take(MAX_SEEDS_SHOWN).enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{seed}")?;
+            }
+            if failure.seeds.len() > MAX_SEEDS_SHOWN {
+                write!(f, ", ... ({} more)", failure.seeds.len() - MAX_SEEDS_SHOWN)?;
+            }
+        }
+        if let Some(time) = &self.time {
+            write!(
+                f,
+                "\nsimulated time of passed seeds: min {:?}, mean {:?}, max {:?}",
+                Duration::from_secs_f64(time.min),
+                Duration::from_secs_f64(time.mean),
+                Duration::from_secs_f64(time.max),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let result = |seed, secs, error: Option<&str>| SeedResult {
+            seed,
+            elapsed: Some(Duration::from_secs(secs)),
+            error: error.map(String::from),
+        };
+        let results = [
+            result(5, 1, Some("boom")),
+            result(2, 1, None),
+            result(3, 2, Some("boom")),
+            result(4, 6, None),
+            result(1, 1, Some("oops")),
+        ];
+        let report = Report::new(&results);
+        assert_eq!(report.first_failure(), Some(1));
+        assert_eq!(
+            report.to_string(),
+            "seed sweep: 2 passed, 3 failed, 5 total\n\
+             failures:\n  \
+             1 seed(s), lowest seed 1: \"oops\"\n    seeds: 1\n  \
+             2 seed(s), lowest seed 3: \"boom\"\n    seeds: 3, 5\n\
+             simulated time of passed seeds: min 1s, mean 3.5s, max 6s"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["failed"], 3);
+        assert_eq!(json["failures"][1]["seeds"], serde_json::json!([3, 5]));
+        assert_eq!(json["time"]["mean"], 3.5);
+    }
+}
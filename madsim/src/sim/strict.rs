@@ -0,0 +1,466 @@
+//! Strict mode to detect escapes from the simulation.
+//!
+//! A dependency that opens a real file or socket inside the simulation breaks
+//! determinism silently. When strict mode is enabled, the following functions
+//! are checked if they are called inside a madsim context:
+//!
+//! - `socket`, `connect`
+//! - `open`, `open64`, `openat`, `openat64` (Linux only)
+//! - `getaddrinfo`
+//! - `pthread_create`, which prints a warning even if strict mode is off
+//!
+//! In [`StrictMode::Warn`], a warning with the backtrace is printed and the call
+//! goes on. In [`StrictMode::Panic`], the call fails with `EPERM`, and the
+//! runtime panics as soon as the current task yields.
+//!
+//! Known-safe calls can be allowed by [`StrictConfig::allow`].
+//!
+//! # Example
+//!
+//! ```
+//! use madsim::{runtime::Runtime, strict::StrictMode, Config};
+//!
+//! let mut config = Config::default();
+//! config.strict.mode = StrictMode::Warn;
+//! config.strict.allow.push("open:/dev/null".into());
+//!
+//! let rt = Runtime::with_seed_and_config(1, config);
+//! rt.block_on(async {
+//!     std::fs::File::open("/dev/null").unwrap();
+//! });
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::CStr,
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+/// Configuration of strict mode.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+pub struct StrictConfig {
+    /// What to do when a call escapes from the simulation.
+    #[serde(default)]
+    pub mode: StrictMode,
+
+    /// Calls that are allowed in strict mode.
+    ///
+    /// Each entry is either a function name, e.g. `getaddrinfo`, which allows
+    /// all calls to the function, or a function name followed by a prefix of the
+    /// argument, e.g. `open:/etc/ssl/` or `connect:127.0.0.1:`.
+    ///
+    /// The argument is the path for `open`, the host name for `getaddrinfo`,
+    /// the socket address for `connect`, and the address family
+    /// (e.g. `AF_INET`) for `socket`.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// What to do when a call escapes from the simulation.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum StrictMode {
+    /// Strict mode is disabled.
+    #[default]
+    Off,
+    /// Print a warning with the backtrace.
+    Warn,
+    /// Fail the call and panic.
+    Panic,
+}
+
+impl FromStr for StrictMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "panic" => Ok(Self::Panic),
+            _ => Err(format!("unknown strict mode: {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for StrictMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Warn => write!(f, "warn"),
+            Self::Panic => write!(f, "panic"),
+        }
+    }
+}
+
+impl StrictConfig {
+    /// Returns true if the call is allowed by the allowlist.
+    fn is_allowed(&self, name: &str, arg: &str) -> bool {
+        self.allow.iter().any(|entry| match entry.split_once(':') {
+            Some((n, prefix)) => n == name && arg.starts_with(prefix),
+            None => entry == name,
+        })
+    }
+}
+
+thread_local! {
+    /// A flag to avoid checking calls made by the checker itself, e.g. when capturing backtrace.
+    static CHECKING: Cell<bool> = Cell::new(false);
+    /// The first violation in the current task.
+    static VIOLATION: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Run `f` without checking the calls it makes.
+///
+/// This is for the simulator itself, e.g. reading `/proc` to detect a debugger.
+pub(crate) fn unchecked<T>(f: impl FnOnce() -> T) -> T {
+    let checking = CHECKING.with(|c| c.replace(true));
+    let ret = f();
+    CHECKING.with(|c| c.set(checking));
+    ret
+}
+
+/// Check a call made by the current thread.
+///
+/// Returns false if the call should fail.
+fn check(name: &str, arg: impl FnOnce() -> String) -> bool {
+    // NOTE: thread locals may have been destroyed when the thread exits
+    if CHECKING.try_with(|c| c.replace(true)).unwrap_or(true) {
+        return true;
+    }
+    let allowed = check_inner(name, arg);
+    CHECKING.with(|c| c.set(false));
+    allowed
+}
+
+fn check_inner(name: &str, arg: impl FnOnce() -> String) -> bool {
+    let mode = match crate::context::try_current(|h| h.config.strict.mode) {
+        Some(mode) if mode != StrictMode::Off => mode,
+        _ => return true,
+    };
+    let arg = arg();
+    let allowed = crate::context::try_current(|h| h.config.strict.is_allowed(name, &arg));
+    if allowed.unwrap_or(true) {
+        return true;
+    }
+    let msg = format!("`{name}({arg})` is called inside the simulation");
+    let backtrace = std::backtrace::Backtrace::force_capture();
+    match mode {
+        StrictMode::Off => true,
+        StrictMode::Warn => {
+            eprintln!("warning: strict mode: {msg}\n{backtrace}");
+            true
+        }
+        StrictMode::Panic => {
+            eprintln!("error: strict mode: {msg}\n{backtrace}");
+            eprintln!("note: add `{name}:{arg}` to `strict.allow` in the config to allow it");
+            VIOLATION.with(|v| {
+                v.borrow_mut().get_or_insert(msg);
+            });
+            false
+        }
+    }
+}
+
+/// Check spawning a system thread.
+///
+/// Returns false if the call should fail.
+pub(crate) fn check_thread_spawn() -> bool {
+    let off = crate::context::try_current(|h| h.config.strict.mode == StrictMode::Off);
+    if off == Some(true) && !CHECKING.try_with(|c| c.get()).unwrap_or(true) {
+        log::warn!(
+            "a system thread is spawned in the simulation, which may break determinism. \
+             use `Runtime::set_allow_system_thread` to allow or forbid it"
+        );
+    }
+    check("pthread_create", String::new)
+}
+
+/// Forget the violation left by a previous runtime on this thread.
+pub(crate) fn reset_violation() {
+    VIOLATION.with(|v| v.borrow_mut().take());
+}
+
+/// Panic if any call has failed in strict mode.
+pub(crate) fn check_violation() {
+    if let Some(msg) = VIOLATION.with(|v| v.borrow_mut().take()) {
+        panic!("strict mode: {msg}");
+    }
+}
+
+unsafe fn set_errno(errno: libc::c_int) {
+    #[cfg(target_os = "linux")]
+    {
+        *libc::__errno_location() = errno;
+    }
+    #[cfg(target_os = "macos")]
+    {
+        *libc::__error() = errno;
+    }
+}
+
+unsafe fn fmt_path(path: *const libc::c_char) -> String {
+    if path.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(path).to_string_lossy().into_owned()
+}
+
+unsafe fn fmt_sockaddr(addr: *const libc::sockaddr) -> String {
+    if addr.is_null() {
+        return String::new();
+    }
+    match (*addr).sa_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = &*(addr as *const libc::sockaddr_in);
+            let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+            SocketAddr::from((ip, u16::from_be(addr.sin_port))).to_string()
+        }
+        libc::AF_INET6 => {
+            let addr = &*(addr as *const libc::sockaddr_in6);
+            let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+            SocketAddr::from((ip, u16::from_be(addr.sin6_port))).to_string()
+        }
+        libc::AF_UNIX => {
+            let addr = &*(addr as *const libc::sockaddr_un);
+            fmt_path(addr.sun_path.as_ptr())
+        }
+        family => format!("family={family}"),
+    }
+}
+
+fn fmt_domain(domain: libc::c_int) -> String {
+    match domain {
+        libc::AF_INET => "AF_INET".into(),
+        libc::AF_INET6 => "AF_INET6".into(),
+        libc::AF_UNIX => "AF_UNIX".into(),
+        _ => domain.to_string(),
+    }
+}
+
+/// Override the libc `socket` function.
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn socket(
+    domain: libc::c_int,
+    ty: libc::c_int,
+    protocol: libc::c_int,
+) -> libc::c_int {
+    if !check("socket", || fmt_domain(domain)) {
+        set_errno(libc::EPERM);
+        return -1;
+    }
+    lazy_static::lazy_static! {
+        static ref SOCKET: unsafe extern "C" fn(
+            domain: libc::c_int,
+            ty: libc::c_int,
+            protocol: libc::c_int,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"socket\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    SOCKET(domain, ty, protocol)
+}
+
+/// Override the libc `connect` function.
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn connect(
+    fd: libc::c_int,
+    addr: *const libc::sockaddr,
+    len: libc::socklen_t,
+) -> libc::c_int {
+    if !check("connect", || fmt_sockaddr(addr)) {
+        set_errno(libc::EPERM);
+        return -1;
+    }
+    lazy_static::lazy_static! {
+        static ref CONNECT: unsafe extern "C" fn(
+            fd: libc::c_int,
+            addr: *const libc::sockaddr,
+            len: libc::socklen_t,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"connect\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    CONNECT(fd, addr, len)
+}
+
+/// Override the libc `getaddrinfo` function.
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn getaddrinfo(
+    node: *const libc::c_char,
+    service: *const libc::c_char,
+    hints: *const libc::addrinfo,
+    res: *mut *mut libc::addrinfo,
+) -> libc::c_int {
+    if !check("getaddrinfo", || fmt_path(node)) {
+        return libc::EAI_FAIL;
+    }
+    lazy_static::lazy_static! {
+        static ref GETADDRINFO: unsafe extern "C" fn(
+            node: *const libc::c_char,
+            service: *const libc::c_char,
+            hints: *const libc::addrinfo,
+            res: *mut *mut libc::addrinfo,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"getaddrinfo\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    GETADDRINFO(node, service, hints, res)
+}
+
+/// Define overrides of `open` family.
+///
+/// NOTE: These functions are variadic in C, but the `mode` argument is always
+/// passed in register on Linux, so they can be defined as normal functions.
+macro_rules! override_open {
+    ($name:ident, $static:ident $(, $dirfd:ident)?) => {
+        #[doc = concat!("Override the libc `", stringify!($name), "` function. For Linux.")]
+        #[no_mangle]
+        #[inline(never)]
+        #[cfg(target_os = "linux")]
+        unsafe extern "C" fn $name(
+            $($dirfd: libc::c_int,)?
+            path: *const libc::c_char,
+            flags: libc::c_int,
+            mode: libc::mode_t,
+        ) -> libc::c_int {
+            if !check("open", || fmt_path(path)) {
+                set_errno(libc::EPERM);
+                return -1;
+            }
+            lazy_static::lazy_static! {
+                static ref $static: unsafe extern "C" fn(
+                    $($dirfd: libc::c_int,)?
+                    path: *const libc::c_char,
+                    flags: libc::c_int,
+                    mode: libc::mode_t,
+                ) -> libc::c_int = unsafe {
+                    let name = concat!(stringify!($name), "\0");
+                    let ptr = libc::dlsym(libc::RTLD_NEXT, name.as_ptr() as _);
+                    assert!(!ptr.is_null());
+                    std::mem::transmute(ptr)
+                };
+            }
+            $static($($dirfd,)? path, flags, mode)
+        }
+    };
+}
+
+override_open!(open, OPEN);
+override_open!(open64, OPEN64);
+override_open!(openat, OPENAT, dirfd);
+override_open!(openat64, OPENAT64, dirfd);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Runtime, Config};
+
+    fn runtime(mode: StrictMode, allow: &[&str]) -> Runtime {
+        let config = Config {
+            strict: StrictConfig {
+                mode,
+                allow: allow.iter().map(|s| s.to_string()).collect(),
+            },
+            ..Default::default()
+        };
+        Runtime::with_seed_and_config(1, config)
+    }
+
+    #[test]
+    fn allowlist() {
+        let config = StrictConfig {
+            mode: StrictMode::Panic,
+            allow: vec!["getaddrinfo".into(), "open:/etc/".into()],
+        };
+        assert!(config.is_allowed("getaddrinfo", "example.com"));
+        assert!(config.is_allowed("open", "/etc/hosts"));
+        assert!(!config.is_allowed("open", "/tmp/data"));
+        assert!(!config.is_allowed("connect", "127.0.0.1:80"));
+    }
+
+    #[test]
+    fn warn() {
+        let rt = runtime(StrictMode::Warn, &[]);
+        rt.block_on(async {
+            std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "strict mode: `socket(AF_INET)` is called inside the simulation")]
+    fn panic() {
+        let rt = runtime(StrictMode::Panic, &[]);
+        rt.block_on(async {
+            let res = std::net::UdpSocket::bind("127.0.0.1:0");
+            assert_eq!(
+                res.unwrap_err().kind(),
+                std::io::ErrorKind::PermissionDenied
+            );
+            crate::task::yield_now().await;
+            unreachable!("the runtime should panic");
+        });
+    }
+
+    #[test]
+    fn allowed() {
+        let rt = runtime(StrictMode::Panic, &["socket:AF_INET"]);
+        rt.block_on(async {
+            std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        });
+    }
+
+    #[test]
+    fn thread_spawn() {
+        let rt = runtime(StrictMode::Panic, &["pthread_create"]);
+        rt.block_on(async {
+            std::thread::spawn(|| {}).join().unwrap();
+        });
+
+        let rt = runtime(StrictMode::Panic, &[]);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            rt.block_on(async {
+                assert!(std::thread::Builder::new().spawn(|| {}).is_err());
+                crate::task::yield_now().await;
+            })
+        }));
+        let err = res.unwrap_err();
+        assert_eq!(
+            crate::task::panic_message(&*err),
+            Some("strict mode: `pthread_create()` is called inside the simulation")
+        );
+    }
+
+    #[test]
+    fn reset_by_new_runtime() {
+        // a violation left by a previous runtime on this thread
+        VIOLATION.with(|v| *v.borrow_mut() = Some("stale".into()));
+        let rt = Runtime::new();
+        rt.block_on(crate::task::yield_now());
+    }
+
+    #[test]
+    fn break_at() {
+        // reading `/proc/self/status` to detect a debugger is not a violation
+        let rt = runtime(StrictMode::Panic, &[]);
+        rt.handle()
+            .task
+            .break_at(std::time::Duration::from_secs(1), None);
+        rt.block_on(async {
+            crate::time::sleep(std::time::Duration::from_secs(2)).await;
+        });
+    }
+}
@@ -0,0 +1,45 @@
+//! Breakpoints at simulated time.
+
+use std::time::Duration;
+
+/// A function called when a breakpoint is hit.
+pub(crate) type Callback = Box<dyn FnOnce() + Send>;
+
+/// Pending breakpoints in the order of time.
+#[derive(Default)]
+pub(crate) struct Breakpoints {
+    /// The time since the start of simulation, and the callback of each breakpoint.
+    ///
+    /// If the callback is `None`, `SIGTRAP` is raised when a debugger is attached.
+    list: Vec<(Duration, Option<Callback>)>,
+}
+
+impl Breakpoints {
+    /// Add a breakpoint at `time`.
+    pub fn add(&mut self, time: Duration, callback: Option<Callback>) {
+        let idx = self.list.partition_point(|(t, _)| *t <= time);
+        self.list.insert(idx, (time, callback));
+    }
+
+    /// Remove and return all breakpoints that have been passed at `now`.
+    pub fn take_due(&mut self, now: Duration) -> Vec<(Duration, Option<Callback>)> {
+        let n = self.list.partition_point(|(t, _)| *t <= now);
+        self.list.drain(..n).collect()
+    }
+}
+
+/// Returns `true` if a debugger is attached to the current process.
+#[cfg(target_os = "linux")]
+pub(crate) fn debugger_attached() -> bool {
+    // this is not an escape from the simulation
+    let status = crate::strict::unchecked(|| std::fs::read_to_string("/proc/self/status"));
+    let status = status.unwrap_or_default();
+    let pid = (status.lines()).find_map(|line| line.strip_prefix("TracerPid:"));
+    matches!(pid, Some(pid) if pid.trim() != "0")
+}
+
+/// Returns `true` if a debugger is attached to the current process.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn debugger_attached() -> bool {
+    false
+}
@@ -0,0 +1,68 @@
+//! Task builder.
+
+use super::{JoinHandle, TaskNodeHandle};
+use crate::runtime::NodeHandle;
+use std::future::Future;
+
+/// Factory which is used to configure the properties of a new task.
+///
+/// # Example
+///
+/// ```
+/// use madsim::{runtime::Runtime, task};
+///
+/// let runtime = Runtime::new();
+/// runtime.block_on(async {
+///     let task = task::Builder::new()
+///         .name("worker")
+///         .spawn(async { 1 });
+///     assert_eq!(task.await.unwrap(), 1);
+/// });
+/// ```
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Default, Debug)]
+pub struct Builder<'a> {
+    name: Option<&'a str>,
+}
+
+impl<'a> Builder<'a> {
+    /// Creates a new task builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns a name to the task which will be spawned.
+    pub fn name(&self, name: &'a str) -> Self {
+        Self { name: Some(name) }
+    }
+
+    /// Spawns a task with this builder's settings on the current node.
+    #[track_caller]
+    pub fn spawn<F>(self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        TaskNodeHandle::current().spawn_named_local(self.name, future)
+    }
+
+    /// Spawns a `!Send` task with this builder's settings on the current node.
+    #[track_caller]
+    pub fn spawn_local<F>(self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        TaskNodeHandle::current().spawn_named_local(self.name, future)
+    }
+
+    /// Spawns a task with this builder's settings on the given node.
+    #[track_caller]
+    pub fn spawn_on<F>(self, future: F, node: &NodeHandle) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        node.task.spawn_named_local(self.name, future)
+    }
+}
@@ -0,0 +1,1763 @@
+//! Asynchronous tasks executor.
+
+use super::{
+    determinism::Event,
+    rand::GlobalRng,
+    runtime::{NodeStatus, PanicPolicy},
+    time::{TimeHandle, TimeRuntime},
+    utils::mpsc,
+};
+use async_task::{FallibleTask, Runnable};
+use futures::FutureExt;
+use rand::Rng;
+use spin::Mutex;
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    future::Future,
+    io,
+    ops::Deref,
+    panic::{AssertUnwindSafe, Location},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+pub(crate) use self::breakpoint::Callback;
+pub use self::builder::Builder;
+pub use self::scheduler::{
+    FifoScheduler, PctScheduler, RandomScheduler, Scheduler, SchedulerConfig,
+};
+pub use tokio::task::yield_now;
+
+mod breakpoint;
+mod builder;
+mod scheduler;
+
+pub(crate) struct Executor {
+    queue: mpsc::Receiver<ReadyTask>,
+    /// Runnable tasks that have been pushed into the scheduler, by task id.
+    ready: Mutex<BTreeMap<Id, ReadyTask>>,
+    scheduler: Mutex<Box<dyn Scheduler>>,
+    /// A flag indicating that ready tasks are not pushed into the scheduler during replay.
+    bypass_scheduler: AtomicBool,
+    handle: TaskHandle,
+    rand: GlobalRng,
+    time: TimeRuntime,
+    time_limit: Option<Duration>,
+}
+
+/// A unique identifier for a node.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct NodeId(u64);
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Node({})", self.0)
+    }
+}
+
+impl NodeId {
+    pub(crate) const fn zero() -> Self {
+        NodeId(0)
+    }
+}
+
+pub(crate) struct TaskInfo {
+    pub node: NodeId,
+    pub name: String,
+    /// The number of CPU cores.
+    pub cores: usize,
+    /// What to do when a task panics.
+    on_panic: PanicPolicy,
+    /// CPU cores.
+    cpu: Pool,
+    /// Threads of the blocking pool.
+    blocking: Pool,
+    /// A flag indicating that the task should be paused.
+    paused: AtomicBool,
+    /// A flag indicating that the task should no longer be executed.
+    killed: AtomicBool,
+    /// The status of the node, shared with node handles and restarted tasks.
+    status: Arc<Mutex<NodeStatus>>,
+}
+
+/// A pool of identical resources, e.g. CPU cores or blocking threads.
+struct Pool {
+    size: usize,
+    /// Idle resources. Tasks wait for them in FIFO order.
+    idle: tokio::sync::Semaphore,
+}
+
+impl Pool {
+    fn new(size: usize) -> Self {
+        Pool {
+            size,
+            idle: tokio::sync::Semaphore::new(size),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Wait until a resource becomes idle and occupy it.
+    ///
+    /// The resource is released when the returned permit is dropped,
+    /// including when the waiting task is aborted or its node is killed.
+    async fn occupy(&self) -> tokio::sync::SemaphorePermit<'_> {
+        self.idle.acquire().await.expect("pool closed")
+    }
+}
+
+/// Metadata of a spawned task.
+pub(crate) struct TaskMeta {
+    pub id: Id,
+    /// The name of the task.
+    pub name: Option<String>,
+    /// The node that the task belongs to.
+    pub info: Arc<TaskInfo>,
+    /// The location where the task was spawned.
+    pub location: &'static Location<'static>,
+    /// The time when the task was spawned.
+    spawn_time: Duration,
+    /// The last time when the task was polled.
+    last_poll: Mutex<Option<Duration>>,
+}
+
+/// A runnable task and its metadata.
+type ReadyTask = (Runnable, Arc<TaskMeta>);
+
+/// A function to spawn the initial task of a node.
+pub(crate) type InitFn = Arc<dyn Fn(&TaskNodeHandle)>;
+
+impl Executor {
+    pub fn new(rand: GlobalRng, scheduler: Box<dyn Scheduler>) -> Self {
+        let (sender, queue) = mpsc::channel();
+        let time = TimeRuntime::new(&rand);
+        Executor {
+            queue,
+            ready: Mutex::new(BTreeMap::new()),
+            scheduler: Mutex::new(scheduler),
+            bypass_scheduler: AtomicBool::new(false),
+            handle: TaskHandle {
+                nodes: Arc::new(Mutex::new(HashMap::new())),
+                breakpoints: Default::default(),
+                spawner: Spawner {
+                    sender,
+                    tasks: Arc::new(Mutex::new(HashMap::new())),
+                    finished: Default::default(),
+                    next_task_id: Arc::new(AtomicU64::new(0)),
+                    time: time.handle().clone(),
+                },
+                next_node_id: Arc::new(AtomicU64::new(1)),
+                main_info: Arc::new(TaskInfo {
+                    node: NodeId::zero(),
+                    name: "main".into(),
+                    cores: 1,
+                    on_panic: PanicPolicy::Propagate,
+                    cpu: Pool::new(1),
+                    blocking: Pool::new(DEFAULT_MAX_BLOCKING_THREADS),
+                    paused: AtomicBool::new(false),
+                    killed: AtomicBool::new(false),
+                    status: Default::default(),
+                }),
+            },
+            time,
+            rand,
+            time_limit: None,
+        }
+    }
+
+    pub fn handle(&self) -> &TaskHandle {
+        &self.handle
+    }
+
+    pub fn time_handle(&self) -> &TimeHandle {
+        self.time.handle()
+    }
+
+    pub fn set_time_limit(&mut self, limit: Duration) {
+        self.time_limit = Some(limit);
+    }
+
+    pub fn set_scheduler(&self, mut scheduler: Box<dyn Scheduler>) {
+        let ready = self.ready.lock();
+        // tasks that have been pushed into the old scheduler
+        if !self.bypass_scheduler.load(Ordering::Relaxed) {
+            for &id in ready.keys() {
+                scheduler.push(id);
+            }
+        }
+        *self.scheduler.lock() = scheduler;
+    }
+
+    #[track_caller]
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        // push the future into ready queue.
+        let info = self.handle.main_info.clone();
+        let (runnable, mut task, _) = unsafe {
+            // Safety: The schedule is not Sync,
+            // the task's Waker must be used and dropped on the original thread.
+            (self.handle.spawner).create_task(info, None, Location::caller(), future)
+        };
+
+        let waker = runnable.waker();
+        runnable.schedule();
+
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            self.run_all_ready();
+            if let Poll::Ready(res) = Pin::new(&mut task).poll(&mut cx) {
+                match res {
+                    Ok(val) => return val,
+                    Err(payload) => std::panic::resume_unwind(payload),
+                }
+            }
+            if !self.advance_to_next_event() {
+                eprintln!("live tasks:\n{}", self.spawner.dump_tasks());
+                panic!("no events, all tasks will block forever");
+            }
+        }
+    }
+
+    /// Run all ready tasks, then advance time to the next timer event.
+    ///
+    /// Returns `false` if there is no ready task and no timer event.
+    pub fn step(&self) -> bool {
+        let ran = self.run_all_ready();
+        self.advance_to_next_event() || ran
+    }
+
+    /// Run tasks and fire timers until the time reaches `deadline`.
+    pub fn run_until(&self, deadline: Instant) {
+        loop {
+            self.run_all_ready();
+            match self.time.next_event_instant() {
+                Some(next) if next <= deadline => self.advance_to_next_event(),
+                _ => break,
+            };
+        }
+        let now = self.time.now_instant();
+        if deadline > now {
+            self.time.advance(deadline - now);
+            self.check_breakpoints();
+        }
+    }
+
+    /// Returns the time of the next timer event.
+    pub fn next_timer_deadline(&self) -> Option<Instant> {
+        self.time.next_event_instant()
+    }
+
+    /// Returns the number of runnable tasks on each node.
+    ///
+    /// Tasks on paused nodes are not runnable.
+    pub fn runnable_tasks(&self) -> HashMap<NodeId, usize> {
+        let mut counts = (self.nodes.lock().keys())
+            .map(|&id| (id, 0))
+            .collect::<HashMap<_, _>>();
+        counts.insert(NodeId::zero(), 0);
+        let mut count = |(_, meta): &ReadyTask| {
+            let info = &meta.info;
+            if !info.killed.load(Ordering::SeqCst) && !info.paused.load(Ordering::SeqCst) {
+                *counts.entry(info.node).or_default() += 1;
+            }
+        };
+        self.ready.lock().values().for_each(&mut count);
+        self.queue.peek_all(|new| new.iter().for_each(&mut count));
+        counts
+    }
+
+    /// Advance time to the next timer event and check the time limit.
+    ///
+    /// Returns `false` if there is no timer event.
+    fn advance_to_next_event(&self) -> bool {
+        if !self.time.advance_to_next_event() {
+            return false;
+        }
+        self.check_breakpoints();
+        if let Some(limit) = self.time_limit {
+            if self.time.handle().elapsed() >= limit {
+                eprintln!("live tasks:\n{}", self.spawner.dump_tasks());
+                panic!("time limit exceeded: {:?}", limit);
+            }
+        }
+        true
+    }
+
+    /// Drain all tasks from ready queue and run them.
+    ///
+    /// Returns `true` if any task was polled.
+    pub fn run_all_ready(&self) -> bool {
+        let mut ran = false;
+        while let Some((runnable, meta)) = self.next_ready() {
+            let info = &meta.info;
+            if info.killed.load(Ordering::SeqCst) {
+                // killed task: ignore
+                continue;
+            } else if info.paused.load(Ordering::SeqCst) {
+                // paused task: push to waiting list
+                let mut nodes = self.nodes.lock();
+                nodes
+                    .get_mut(&info.node)
+                    .unwrap()
+                    .paused
+                    .push((runnable, meta));
+                continue;
+            }
+            // run the task
+            ran = true;
+            *meta.last_poll.lock() = Some(self.time.handle().elapsed());
+            self.rand.log_event(|| Event::Poll {
+                node: info.node,
+                node_name: info.name.clone(),
+                task: meta.id,
+                name: meta.name.clone(),
+            });
+            let _guard = crate::context::enter_task(meta.clone());
+            runnable.run();
+            crate::strict::check_violation();
+            // advance time: 50-100ns
+            let dur = (self.rand.recorder())
+                .poll(|| Duration::from_nanos(self.rand.with(|rng| rng.gen_range(50..100))));
+            self.time.advance(dur);
+            self.check_breakpoints();
+        }
+        ran
+    }
+
+    /// Stop at breakpoints that have been passed.
+    fn check_breakpoints(&self) {
+        let now = self.time.handle().elapsed();
+        let due = self.breakpoints.lock().take_due(now);
+        for (time, callback) in due {
+            eprintln!(
+                "breakpoint at {:?} hit at {:?}\n{}",
+                time,
+                now,
+                self.dump_state()
+            );
+            match callback {
+                Some(f) => f(),
+                None if breakpoint::debugger_attached() => unsafe {
+                    libc::raise(libc::SIGTRAP);
+                },
+                None => eprintln!("note: no debugger attached, continuing"),
+            }
+        }
+    }
+
+    /// Dump the state of all nodes and live tasks.
+    fn dump_state(&self) -> String {
+        let runnable = self.runnable_tasks();
+        let mut nodes = (self.nodes.lock().iter())
+            .map(|(id, node)| {
+                (
+                    *id,
+                    node.info.clone(),
+                    *node.info.status.lock(),
+                    node.paused.len(),
+                )
+            })
+            .collect::<Vec<_>>();
+        nodes.push((
+            NodeId::zero(),
+            self.main_info.clone(),
+            NodeStatus::Running,
+            0,
+        ));
+        nodes.sort_by_key(|(id, ..)| *id);
+        let mut s = String::from("nodes:\n");
+        for (id, info, status, parked) in nodes {
+            let _ = write!(
+                s,
+                "  {} {:?}: {}, {} runnable task(s)",
+                id,
+                info.name,
+                status,
+                runnable.get(&id).copied().unwrap_or_default()
+            );
+            if parked != 0 {
+                let _ = write!(s, ", {} parked task(s)", parked);
+            }
+            s.push('\n');
+        }
+        let _ = write!(s, "live tasks:\n{}", self.spawner.dump_tasks());
+        s
+    }
+
+    /// Pick the next task to run from ready queue.
+    fn next_ready(&self) -> Option<ReadyTask> {
+        let mut ready = self.ready.lock();
+        let mut scheduler = self.scheduler.lock();
+        let recorder = self.rand.recorder();
+        if recorder.is_replaying() {
+            self.bypass_scheduler.store(true, Ordering::Relaxed);
+        }
+        for id in self.spawner.finished.lock().drain(..) {
+            scheduler.remove(id);
+        }
+        let new = (self.queue.try_recv_all().into_iter())
+            .map(|task| (task.1.id, task))
+            .collect::<Vec<_>>();
+        let new_ids = new.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        ready.extend(new);
+        if ready.is_empty() {
+            return None;
+        }
+        let id = recorder.pick(
+            |id| ready.contains_key(&Id(id)),
+            || {
+                if self.bypass_scheduler.swap(false, Ordering::Relaxed) {
+                    // replay has stopped, hand over all ready tasks to the scheduler
+                    for &id in ready.keys() {
+                        scheduler.push(id);
+                    }
+                } else {
+                    for &id in &new_ids {
+                        scheduler.push(id);
+                    }
+                }
+                let id = scheduler.pop(&self.rand).expect("scheduler lost a task");
+                assert!(
+                    ready.contains_key(&id),
+                    "scheduler returned an unknown task"
+                );
+                id.0
+            },
+        );
+        Some(ready.remove(&Id(id)).unwrap())
+    }
+}
+
+impl Deref for Executor {
+    type Target = TaskHandle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct TaskHandle {
+    spawner: Spawner,
+    nodes: Arc<Mutex<HashMap<NodeId, Node>>>,
+    breakpoints: Arc<Mutex<breakpoint::Breakpoints>>,
+    next_node_id: Arc<AtomicU64>,
+    /// Task info of the main node.
+    main_info: Arc<TaskInfo>,
+}
+
+struct Node {
+    info: Arc<TaskInfo>,
+    paused: Vec<ReadyTask>,
+    /// A function to spawn the initial task.
+    init: Option<InitFn>,
+}
+
+impl TaskHandle {
+    /// Kill all tasks of the node.
+    pub fn kill(&self, id: NodeId) {
+        log::debug!("kill {}", id);
+        let mut nodes = self.nodes.lock();
+        let node = nodes.get_mut(&id).expect("node not found");
+        node.paused.clear();
+        let new_info = Arc::new(TaskInfo {
+            node: id,
+            name: node.info.name.clone(),
+            cores: node.info.cores,
+            on_panic: node.info.on_panic,
+            cpu: Pool::new(node.info.cores),
+            blocking: Pool::new(node.info.blocking.size()),
+            paused: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+            status: node.info.status.clone(),
+        });
+        let old_info = std::mem::replace(&mut node.info, new_info);
+        old_info.killed.store(true, Ordering::SeqCst);
+        *node.info.status.lock() = NodeStatus::Killed;
+    }
+
+    /// Kill all tasks of the node and restart the initial task.
+    pub fn restart(&self, id: NodeId) {
+        self.kill(id);
+        log::debug!("restart {}", id);
+        let nodes = self.nodes.lock();
+        let node = nodes.get(&id).expect("node not found");
+        *node.info.status.lock() = NodeStatus::Running;
+        if let Some(init) = &node.init {
+            init(&TaskNodeHandle {
+                spawner: self.spawner.clone(),
+                info: node.info.clone(),
+            });
+        }
+    }
+
+    /// Pause all tasks of the node.
+    pub fn pause(&self, id: NodeId) {
+        log::debug!("pause {}", id);
+        let nodes = self.nodes.lock();
+        let node = nodes.get(&id).expect("node not found");
+        node.info.paused.store(true, Ordering::SeqCst);
+        let mut status = node.info.status.lock();
+        if *status != NodeStatus::Killed {
+            *status = NodeStatus::Paused;
+        }
+    }
+
+    /// Resume the execution of the address.
+    pub fn resume(&self, id: NodeId) {
+        log::debug!("resume {}", id);
+        let mut nodes = self.nodes.lock();
+        let node = nodes.get_mut(&id).expect("node not found");
+        node.info.paused.store(false, Ordering::SeqCst);
+        let mut status = node.info.status.lock();
+        if *status != NodeStatus::Killed {
+            *status = NodeStatus::Running;
+        }
+        drop(status);
+
+        // take paused tasks from waiting list and push them to ready queue
+        for task in node.paused.drain(..) {
+            self.spawner.sender.send(task).unwrap();
+        }
+    }
+
+    /// Kill all tasks of the node and remove it.
+    pub fn remove_node(&self, id: NodeId) {
+        self.kill(id);
+        log::debug!("remove {}", id);
+        let node = self.nodes.lock().remove(&id).unwrap();
+        *node.info.status.lock() = NodeStatus::Removed;
+    }
+
+    /// Returns the function to spawn the initial task of the node.
+    pub fn get_init(&self, id: NodeId) -> Option<InitFn> {
+        self.nodes.lock().get(&id)?.init.clone()
+    }
+
+    /// Stop when the simulated time passes `time`.
+    ///
+    /// If `callback` is `None`, `SIGTRAP` is raised when a debugger is attached.
+    pub fn break_at(&self, time: Duration, callback: Option<Callback>) {
+        self.breakpoints.lock().add(time, callback);
+    }
+
+    /// Create a new node.
+    pub fn create_node(
+        &self,
+        name: Option<String>,
+        init: Option<InitFn>,
+        cores: Option<usize>,
+        max_blocking_threads: Option<usize>,
+        on_panic: PanicPolicy,
+    ) -> TaskNodeHandle {
+        let id = NodeId(self.next_node_id.fetch_add(1, Ordering::SeqCst));
+        log::debug!("create {}", id);
+        let cores = cores.unwrap_or(1);
+        let info = Arc::new(TaskInfo {
+            node: id,
+            name: name.unwrap_or_else(|| format!("node-{}", id.0)),
+            cores,
+            on_panic,
+            cpu: Pool::new(cores),
+            blocking: Pool::new(max_blocking_threads.unwrap_or(DEFAULT_MAX_BLOCKING_THREADS)),
+            paused: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+            status: Arc::new(Mutex::new(NodeStatus::Running)),
+        });
+        let handle = TaskNodeHandle {
+            spawner: self.spawner.clone(),
+            info: info.clone(),
+        };
+        if let Some(init) = &init {
+            init(&handle);
+        }
+        let node = Node {
+            info,
+            paused: vec![],
+            init,
+        };
+        self.nodes.lock().insert(id, node);
+        handle
+    }
+
+    /// Get the node handle.
+    pub fn get_node(&self, id: NodeId) -> Option<TaskNodeHandle> {
+        let info = match id {
+            NodeId(0) => self.main_info.clone(),
+            _ => self.nodes.lock().get(&id)?.info.clone(),
+        };
+        Some(TaskNodeHandle {
+            spawner: self.spawner.clone(),
+            info,
+        })
+    }
+
+    /// Returns the IDs of all nodes in ascending order, excluding the main node.
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        let mut ids = self.nodes.lock().keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Returns the ID of the first created node with the given name.
+    pub fn node_by_name(&self, name: &str) -> Option<NodeId> {
+        (self.nodes.lock().iter())
+            .filter(|(_, node)| node.info.name == name)
+            .map(|(id, _)| *id)
+            .min()
+    }
+}
+
+/// Creates tasks and keeps track of live tasks.
+#[derive(Clone)]
+struct Spawner {
+    sender: mpsc::Sender<ReadyTask>,
+    /// All live tasks.
+    tasks: Arc<Mutex<HashMap<Id, Arc<TaskMeta>>>>,
+    /// Tasks finished since the scheduler was last notified.
+    finished: Arc<Mutex<Vec<Id>>>,
+    next_task_id: Arc<AtomicU64>,
+    time: TimeHandle,
+}
+
+impl Spawner {
+    /// Create a new task on the node and register it as a live task.
+    ///
+    /// # Safety
+    ///
+    /// The returned `Runnable` must be used and dropped on the original thread.
+    unsafe fn create_task<F: Future>(
+        &self,
+        info: Arc<TaskInfo>,
+        name: Option<String>,
+        location: &'static Location<'static>,
+        future: F,
+    ) -> (
+        Runnable,
+        async_task::Task<std::thread::Result<F::Output>>,
+        Id,
+    ) {
+        let meta = Arc::new(TaskMeta {
+            id: Id(self.next_task_id.fetch_add(1, Ordering::SeqCst)),
+            name,
+            info: info.clone(),
+            location,
+            spawn_time: self.time.elapsed(),
+            last_poll: Mutex::new(None),
+        });
+        let id = meta.id;
+        self.tasks.lock().insert(id, meta.clone());
+        // unregister the task when the future is dropped
+        let guard = Unregister {
+            tasks: self.tasks.clone(),
+            finished: self.finished.clone(),
+            id,
+        };
+        let future = async move {
+            let _guard = guard;
+            let res = AssertUnwindSafe(future).catch_unwind().await;
+            if res.is_err() {
+                match info.on_panic {
+                    PanicPolicy::Propagate => {}
+                    PanicPolicy::Kill => {
+                        log::warn!("task {} panicked, kill {}", id, info.node);
+                        crate::context::current(|h| h.kill_process(info.node));
+                    }
+                    PanicPolicy::Restart => {
+                        log::warn!("task {} panicked, restart {}", id, info.node);
+                        crate::context::current(|h| h.restart_process(info.node));
+                    }
+                }
+            }
+            match res {
+                Err(payload) if info.on_panic == PanicPolicy::Propagate => {
+                    std::panic::resume_unwind(payload)
+                }
+                res => res,
+            }
+        };
+        let sender = self.sender.clone();
+        let (runnable, task) = async_task::spawn_unchecked(future, move |runnable| {
+            log::trace!("wake task {}", id);
+            let _ = sender.send((runnable, meta.clone()));
+        });
+        (runnable, task, id)
+    }
+
+    /// Returns a table of all live tasks, grouped by node.
+    fn dump_tasks(&self) -> String {
+        let mut tasks: Vec<_> = (self.tasks.lock().values())
+            .filter(|task| !task.info.killed.load(Ordering::SeqCst))
+            .cloned()
+            .collect();
+        tasks.sort_by_key(|task| (task.info.node, task.id));
+        let fmt_time = |t: Option<Duration>| match t {
+            Some(t) => format!("{:.6}s", t.as_secs_f64()),
+            None => "-".into(),
+        };
+        let mut table = String::new();
+        let mut last_node = None;
+        for task in tasks {
+            let info = &task.info;
+            if last_node != Some(info.node) {
+                last_node = Some(info.node);
+                let paused = info.paused.load(Ordering::SeqCst);
+                let _ = writeln!(
+                    table,
+                    "{} {:?}{}",
+                    info.node,
+                    info.name,
+                    if paused { " (paused)" } else { "" }
+                );
+                let _ = writeln!(
+                    table,
+                    "  {:>8}  {:<16}  {:>14}  {:>14}  LOCATION",
+                    "TASK", "NAME", "SPAWNED", "LAST POLL"
+                );
+            }
+            let _ = writeln!(
+                table,
+                "  {:>8}  {:<16}  {:>14}  {:>14}  {}",
+                task.id,
+                task.name.as_deref().unwrap_or("-"),
+                fmt_time(Some(task.spawn_time)),
+                fmt_time(*task.last_poll.lock()),
+                task.location,
+            );
+        }
+        table
+    }
+}
+
+/// An RAII guard to unregister a task from the live task list.
+struct Unregister {
+    tasks: Arc<Mutex<HashMap<Id, Arc<TaskMeta>>>>,
+    finished: Arc<Mutex<Vec<Id>>>,
+    id: Id,
+}
+
+impl Drop for Unregister {
+    fn drop(&mut self) {
+        self.tasks.lock().remove(&self.id);
+        self.finished.lock().push(self.id);
+    }
+}
+
+/// A handle to spawn tasks on a node.
+#[derive(Clone)]
+pub struct TaskNodeHandle {
+    spawner: Spawner,
+    info: Arc<TaskInfo>,
+}
+
+impl TaskNodeHandle {
+    fn current() -> Self {
+        let info = crate::context::current_task();
+        let spawner = crate::context::current(|h| h.task.spawner.clone());
+        TaskNodeHandle { spawner, info }
+    }
+
+    pub(crate) fn id(&self) -> NodeId {
+        self.info.node
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.info.name
+    }
+
+    pub(crate) fn status(&self) -> NodeStatus {
+        *self.info.status.lock()
+    }
+
+    pub(crate) fn cores(&self) -> usize {
+        self.info.cores
+    }
+
+    pub(crate) fn max_blocking_threads(&self) -> usize {
+        self.info.blocking.size()
+    }
+
+    pub(crate) fn on_panic(&self) -> PanicPolicy {
+        self.info.on_panic
+    }
+
+    /// Spawns a new asynchronous task, returning a [`JoinHandle`] for it.
+    #[track_caller]
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_local(future)
+    }
+
+    /// Spawns a `!Send` future on the local task set.
+    #[track_caller]
+    pub fn spawn_local<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_named_local(None, future)
+    }
+
+    /// Spawns a `!Send` future with an optional name.
+    #[track_caller]
+    pub(crate) fn spawn_named_local<F>(
+        &self,
+        name: Option<&str>,
+        future: F,
+    ) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        let name = name.map(String::from);
+        let (runnable, task, id) = unsafe {
+            // Safety: The schedule is not Sync,
+            // the task's Waker must be used and dropped on the original thread.
+            (self.spawner).create_task(self.info.clone(), name, Location::caller(), future)
+        };
+        log::trace!("spawn task {}", id);
+        runnable.schedule();
+
+        JoinHandle {
+            id,
+            task: Mutex::new(Some(task.fallible())),
+        }
+    }
+}
+
+/// Spawns a new asynchronous task, returning a [`JoinHandle`] for it.
+#[track_caller]
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let handle = TaskNodeHandle::current();
+    handle.spawn(future)
+}
+
+/// Spawns a `!Send` future on the local task set.
+#[track_caller]
+pub fn spawn_local<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let handle = TaskNodeHandle::current();
+    handle.spawn_local(future)
+}
+
+/// The default number of threads in the blocking pool of a node.
+const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+
+/// Runs the provided closure on a thread where blocking is acceptable.
+///
+/// The closure runs in the blocking pool of the current node. It waits for an
+/// idle thread like any other call, but takes no simulated time once it gets one,
+/// so it only queues behind calls made by [`spawn_blocking_with_cost`].
+/// Use that function to simulate a slow call.
+#[track_caller]
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    spawn_blocking_with_cost(Duration::ZERO, f)
+}
+
+/// Runs the provided closure on a thread where blocking is acceptable,
+/// which takes `cost` of simulated time.
+///
+/// Each node has a blocking pool with a limited number of threads, set by
+/// [`NodeBuilder::max_blocking_threads`]. The closure waits in a queue until a
+/// thread becomes idle, then occupies it for `cost`. It is called at the end
+/// of the period, so its effects become visible when the call completes.
+/// If the task is aborted or its node is killed, the thread is released.
+///
+/// # Example
+///
+/// ```
+/// use madsim::{runtime::Runtime, task, time::{Duration, TimeHandle}};
+///
+/// let runtime = Runtime::new();
+/// let node = runtime.create_node().max_blocking_threads(1).build();
+/// let f = node.spawn(async {
+///     let time = TimeHandle::current();
+///     let t0 = time.elapsed();
+///     let t1 = task::spawn_blocking_with_cost(Duration::from_secs(1), || 1);
+///     let t2 = task::spawn_blocking_with_cost(Duration::from_secs(1), || 2);
+///     assert_eq!(t1.await.unwrap() + t2.await.unwrap(), 3);
+///     // 2 calls are queued on 1 thread
+///     assert_eq!((time.elapsed() - t0).as_secs(), 2);
+/// });
+/// runtime.block_on(f).unwrap();
+/// ```
+///
+/// [`NodeBuilder::max_blocking_threads`]: crate::runtime::NodeBuilder::max_blocking_threads
+#[track_caller]
+pub fn spawn_blocking_with_cost<F, R>(cost: Duration, f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let handle = TaskNodeHandle::current();
+    let info = handle.info.clone();
+    handle.spawn(async move {
+        let _thread = info.blocking.occupy().await;
+        if !cost.is_zero() {
+            TimeHandle::current().sleep(cost).await;
+        }
+        f()
+    })
+}
+
+/// Consumes simulated CPU time on the current node.
+///
+/// The task occupies one CPU core of the node for `dur`. A node has as many
+/// cores as set by [`NodeBuilder::cores`], so at most that many tasks can consume
+/// CPU time at once. The others wait in a queue until a core becomes idle.
+///
+/// # Example
+///
+/// ```
+/// use madsim::{runtime::Runtime, task, time::{Duration, TimeHandle}};
+///
+/// let runtime = Runtime::new();
+/// let node = runtime.create_node().cores(2).build();
+/// let f = node.spawn(async {
+///     let time = TimeHandle::current();
+///     let t0 = time.elapsed();
+///     let tasks: Vec<_> = (0..4)
+///         .map(|_| task::spawn(task::consume_cpu(Duration::from_secs(1))))
+///         .collect();
+///     futures::future::join_all(tasks).await;
+///     // 4 seconds of work on 2 cores
+///     assert_eq!((time.elapsed() - t0).as_secs(), 2);
+/// });
+/// runtime.block_on(f).unwrap();
+/// ```
+///
+/// [`NodeBuilder::cores`]: crate::runtime::NodeBuilder::cores
+pub async fn consume_cpu(dur: Duration) {
+    let info = crate::context::current_task();
+    let _core = info.cpu.occupy().await;
+    TimeHandle::current().sleep(dur).await;
+}
+
+/// An opaque ID that uniquely identifies a task relative to all other currently running tasks.
+///
+/// IDs are allocated per runtime, so they are deterministic for a given seed.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Id(u64);
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// An owned permission to join on a task (await its termination).
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    id: Id,
+    task: Mutex<Option<FallibleTask<std::thread::Result<T>>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Abort the task associated with the handle.
+    pub fn abort(&self) {
+        self.task.lock().take();
+    }
+
+    /// Cancel the task when this handle is dropped.
+    #[doc(hidden)]
+    pub fn cancel_on_drop(self) -> FallibleTask<std::thread::Result<T>> {
+        self.task.lock().take().unwrap()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        let id = self.id;
+        let mut task = self.task.lock();
+        let task = match task.as_mut() {
+            Some(task) => task,
+            // aborted
+            None => return Poll::Ready(Err(JoinError::cancelled(id))),
+        };
+        std::pin::Pin::new(task).poll(cx).map(|res| match res {
+            Some(Ok(val)) => Ok(val),
+            Some(Err(payload)) => Err(JoinError {
+                id,
+                repr: Repr::Panic(payload),
+            }),
+            // the node is killed or the task is aborted
+            None => Err(JoinError::cancelled(id)),
+        })
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.lock().take() {
+            task.detach();
+        }
+    }
+}
+
+/// Task failed to execute to completion.
+pub struct JoinError {
+    id: Id,
+    repr: Repr,
+}
+
+enum Repr {
+    Cancelled,
+    Panic(Box<dyn Any + Send + 'static>),
+}
+
+impl JoinError {
+    fn cancelled(id: Id) -> Self {
+        JoinError {
+            id,
+            repr: Repr::Cancelled,
+        }
+    }
+
+    /// Returns a task ID that identifies the task which errored relative to other currently spawned tasks.
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns true if the error was caused by the task being cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.repr, Repr::Cancelled)
+    }
+
+    /// Returns true if the error was caused by the task panicking.
+    pub fn is_panic(&self) -> bool {
+        matches!(self.repr, Repr::Panic(_))
+    }
+
+    /// Consumes the join error, returning the object with which the task panicked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the error was not caused by a panic.
+    #[track_caller]
+    pub fn into_panic(self) -> Box<dyn Any + Send + 'static> {
+        self.try_into_panic()
+            .expect("`JoinError` reason is not a panic.")
+    }
+
+    /// Consumes the join error, returning the object with which the task panicked
+    /// if the task terminated due to a panic. Otherwise, `self` is returned.
+    pub fn try_into_panic(self) -> Result<Box<dyn Any + Send + 'static>, JoinError> {
+        match self.repr {
+            Repr::Panic(payload) => Ok(payload),
+            _ => Err(self),
+        }
+    }
+
+    /// Returns the panic message if it is a string.
+    fn panic_message(&self) -> Option<&str> {
+        match &self.repr {
+            Repr::Panic(payload) => panic_message(&**payload),
+            Repr::Cancelled => None,
+        }
+    }
+}
+
+/// Returns the message of a panic payload if it is a string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    (payload.downcast_ref::<&str>().copied())
+        .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.repr, self.panic_message()) {
+            (Repr::Cancelled, _) => write!(f, "task {} was cancelled", self.id),
+            (Repr::Panic(_), Some(msg)) => {
+                write!(f, "task {} panicked with message {:?}", self.id, msg)
+            }
+            (Repr::Panic(_), None) => write!(f, "task {} panicked", self.id),
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.repr, self.panic_message()) {
+            (Repr::Cancelled, _) => write!(f, "JoinError::Cancelled({:?})", self.id),
+            (Repr::Panic(_), Some(msg)) => {
+                write!(f, "JoinError::Panic({:?}, {:?}, ...)", self.id, msg)
+            }
+            (Repr::Panic(_), None) => write!(f, "JoinError::Panic({:?}, ...)", self.id),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+impl From<JoinError> for io::Error {
+    fn from(src: JoinError) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Other,
+            match src.repr {
+                Repr::Cancelled => "task was cancelled",
+                Repr::Panic(_) => "task panicked",
+            },
+        )
+    }
+}
+
+/// Detect spawning system threads in the simulation.
+///
+/// See [`Runtime::set_allow_system_thread`](crate::runtime::Runtime::set_allow_system_thread).
+///
+/// Ref: <https://man7.org/linux/man-pages/man3/pthread_create.3.html>
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn pthread_create(
+    thread: *mut libc::pthread_t,
+    attr: *const libc::pthread_attr_t,
+    start_routine: extern "C" fn(*mut libc::c_void) -> *mut libc::c_void,
+    arg: *mut libc::c_void,
+) -> libc::c_int {
+    match crate::context::try_current(|h| *h.allow_system_thread.lock()) {
+        None | Some(Some(true)) => {}
+        Some(Some(false)) => {
+            eprintln!("attempt to spawn a system thread in simulation.");
+            eprintln!("note: try to use `madsim::task::spawn` instead.");
+            eprintln!("note: or use `Runtime::set_allow_system_thread` to allow it.");
+            return libc::EPERM;
+        }
+        Some(None) => {
+            if !crate::strict::check_thread_spawn() {
+                return libc::EPERM;
+            }
+        }
+    }
+    lazy_static::lazy_static! {
+        static ref PTHREAD_CREATE: unsafe extern "C" fn(
+            thread: *mut libc::pthread_t,
+            attr: *const libc::pthread_attr_t,
+            start_routine: extern "C" fn(*mut libc::c_void) -> *mut libc::c_void,
+            arg: *mut libc::c_void,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"pthread_create\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    PTHREAD_CREATE(thread, attr, start_routine, arg)
+}
+
+/// For `std::thread::available_parallelism` on Linux.
+///
+/// Ref: <https://man7.org/linux/man-pages/man2/sched_setaffinity.2.html>
+#[no_mangle]
+#[inline(never)]
+#[cfg(target_os = "linux")]
+unsafe extern "C" fn sched_getaffinity(
+    pid: libc::pid_t,
+    cpusetsize: libc::size_t,
+    cpuset: *mut libc::cpu_set_t,
+) -> libc::c_int {
+    if let Some(info) = crate::context::try_current_task() {
+        assert_eq!(cpusetsize, std::mem::size_of::<libc::cpu_set_t>());
+        assert!(cpusetsize * 8 >= info.cores as _);
+        for i in 0..info.cores {
+            libc::CPU_SET(i, &mut *cpuset);
+        }
+        return 0;
+    }
+    lazy_static::lazy_static! {
+        static ref SCHED_GETAFFINITY: unsafe extern "C" fn(
+            pid: libc::pid_t,
+            cpusetsize: libc::size_t,
+            cpuset: *mut libc::cpu_set_t,
+        ) -> libc::c_int = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"sched_getaffinity\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    SCHED_GETAFFINITY(pid, cpusetsize, cpuset)
+}
+
+/// For `std::thread::available_parallelism` on macOS.
+///
+/// Ref: <https://man7.org/linux/man-pages/man3/sysconf.3.html>
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn sysconf(name: libc::c_int) -> libc::c_long {
+    if name == libc::_SC_NPROCESSORS_ONLN {
+        if let Some(info) = crate::context::try_current_task() {
+            return info.cores as _;
+        }
+    }
+    lazy_static::lazy_static! {
+        static ref SYSCONF: unsafe extern "C" fn(name: libc::c_int) -> libc::c_long = unsafe {
+            let ptr = libc::dlsym(libc::RTLD_NEXT, b"sysconf\0".as_ptr() as _);
+            assert!(!ptr.is_null());
+            std::mem::transmute(ptr)
+        };
+    }
+    SYSCONF(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        runtime::{Handle, PanicPolicy, Runtime},
+        time,
+    };
+    use std::{collections::HashSet, sync::atomic::AtomicUsize, time::Duration};
+
+    #[test]
+    fn spawn_in_block_on() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            spawn(async { 1 }).await.unwrap();
+            spawn_local(async { 2 }).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn kill() {
+        let runtime = Runtime::new();
+        let node1 = runtime.create_node().build();
+        let node2 = runtime.create_node().build();
+
+        let flag1 = Arc::new(AtomicUsize::new(0));
+        let flag2 = Arc::new(AtomicUsize::new(0));
+
+        let flag1_ = flag1.clone();
+        node1.spawn(async move {
+            loop {
+                time::sleep(Duration::from_secs(2)).await;
+                flag1_.fetch_add(2, Ordering::SeqCst);
+            }
+        });
+
+        let flag2_ = flag2.clone();
+        node2.spawn(async move {
+            loop {
+                time::sleep(Duration::from_secs(2)).await;
+                flag2_.fetch_add(2, Ordering::SeqCst);
+            }
+        });
+
+        runtime.block_on(async move {
+            let t0 = time::Instant::now();
+
+            time::sleep_until(t0 + Duration::from_secs(3)).await;
+            assert_eq!(flag1.load(Ordering::SeqCst), 2);
+            assert_eq!(flag2.load(Ordering::SeqCst), 2);
+            Handle::current().kill(node1.id());
+            Handle::current().kill(node1.id());
+
+            time::sleep_until(t0 + Duration::from_secs(5)).await;
+            assert_eq!(flag1.load(Ordering::SeqCst), 2);
+            assert_eq!(flag2.load(Ordering::SeqCst), 4);
+        });
+    }
+
+    #[test]
+    fn restart() {
+        let runtime = Runtime::new();
+
+        let flag = Arc::new(AtomicUsize::new(0));
+
+        let flag_ = flag.clone();
+        let node = runtime
+            .create_node()
+            .init(move || {
+                let flag = flag_.clone();
+                async move {
+                    // set flag to 0, then +2 every 2s
+                    flag.store(0, Ordering::SeqCst);
+                    loop {
+                        time::sleep(Duration::from_secs(2)).await;
+                        flag.fetch_add(2, Ordering::SeqCst);
+                    }
+                }
+            })
+            .build();
+
+        runtime.block_on(async move {
+            let t0 = time::Instant::now();
+
+            time::sleep_until(t0 + Duration::from_secs(3)).await;
+            assert_eq!(flag.load(Ordering::SeqCst), 2);
+            Handle::current().kill(node.id());
+            Handle::current().restart(node.id());
+
+            time::sleep_until(t0 + Duration::from_secs(6)).await;
+            assert_eq!(flag.load(Ordering::SeqCst), 2);
+
+            time::sleep_until(t0 + Duration::from_secs(8)).await;
+            assert_eq!(flag.load(Ordering::SeqCst), 4);
+        });
+    }
+
+    #[test]
+    fn pause_resume() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+
+        let flag = Arc::new(AtomicUsize::new(0));
+        let flag_ = flag.clone();
+        node.spawn(async move {
+            loop {
+                time::sleep(Duration::from_secs(2)).await;
+                flag_.fetch_add(2, Ordering::SeqCst);
+            }
+        });
+
+        runtime.block_on(async move {
+            let t0 = time::Instant::now();
+
+            time::sleep_until(t0 + Duration::from_secs(3)).await;
+            assert_eq!(flag.load(Ordering::SeqCst), 2);
+            Handle::current().pause(node.id());
+            Handle::current().pause(node.id());
+
+            time::sleep_until(t0 + Duration::from_secs(5)).await;
+            assert_eq!(flag.load(Ordering::SeqCst), 2);
+
+            Handle::current().resume(node.id());
+            Handle::current().resume(node.id());
+            time::sleep_until(t0 + Duration::from_secs_f32(5.5)).await;
+            assert_eq!(flag.load(Ordering::SeqCst), 4);
+        });
+    }
+
+    #[test]
+    fn spawn_system_thread() {
+        let runtime = Runtime::new();
+        // allowed with a warning by default
+        runtime.block_on(async {
+            std::thread::spawn(|| {}).join().unwrap();
+        });
+        runtime.set_allow_system_thread(false);
+        runtime.block_on(async {
+            let err = std::thread::Builder::new().spawn(|| {}).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        });
+        runtime.set_allow_system_thread(true);
+        runtime.block_on(async {
+            std::thread::spawn(|| {}).join().unwrap();
+        });
+    }
+
+    #[test]
+    fn step_without_progress() {
+        let runtime = Runtime::new();
+        let node1 = runtime.create_node().build();
+        let node2 = runtime.create_node().build();
+        node1.spawn(async {});
+        node2.spawn(async {});
+        // tasks of paused and killed nodes are taken from the ready queue but not polled
+        runtime.handle().pause(node1.id());
+        runtime.handle().kill(node2.id());
+        assert!(!runtime.step());
+
+        runtime.handle().resume(node1.id());
+        assert!(runtime.step());
+        assert!(!runtime.step());
+    }
+
+    #[test]
+    fn random_select_from_ready_tasks() {
+        let mut seqs = HashSet::new();
+        for seed in 0..10 {
+            let runtime = Runtime::with_seed_and_config(seed, crate::Config::default());
+            let seq = runtime.block_on(async {
+                let (tx, rx) = std::sync::mpsc::channel();
+                let mut tasks = vec![];
+                for i in 0..3 {
+                    let tx = tx.clone();
+                    tasks.push(spawn(async move {
+                        for j in 0..5 {
+                            tx.send(i * 10 + j).unwrap();
+                            tokio::task::yield_now().await;
+                        }
+                    }));
+                }
+                drop(tx);
+                futures::future::join_all(tasks).await;
+                rx.into_iter().collect::<Vec<_>>()
+            });
+            seqs.insert(seq);
+        }
+        assert_eq!(seqs.len(), 10);
+    }
+
+    #[test]
+    fn deterministic_std_thread_available_parallelism() {
+        let runtime = Runtime::new();
+        runtime.block_on(async move {
+            // available_parallelism is 1 by default
+            assert_eq!(std::thread::available_parallelism().unwrap().get(), 1);
+        });
+        let f1 = runtime.create_node().build().spawn(async move {
+            // available_parallelism is 1 by default
+            assert_eq!(std::thread::available_parallelism().unwrap().get(), 1);
+        });
+        let f2 = runtime.create_node().cores(128).build().spawn(async move {
+            assert_eq!(std::thread::available_parallelism().unwrap().get(), 128);
+        });
+        runtime.block_on(f1).unwrap();
+        runtime.block_on(f2).unwrap();
+    }
+
+    #[test]
+    fn join_error() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let task = spawn(std::future::pending::<()>());
+            task.abort();
+            let err = task.await.unwrap_err();
+            assert!(err.is_cancelled());
+            assert!(err.try_into_panic().is_err());
+        });
+    }
+
+    #[test]
+    fn panic_policy() {
+        let runtime = Runtime::new();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let starts0 = starts.clone();
+        let node1 = runtime
+            .create_node()
+            .on_panic(PanicPolicy::Kill)
+            .init(move || {
+                let starts = starts0.clone();
+                async move {
+                    starts.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build();
+        let starts0 = starts.clone();
+        let node2 = runtime
+            .create_node()
+            .on_panic(PanicPolicy::Restart)
+            .init(move || {
+                let starts = starts0.clone();
+                async move {
+                    starts.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build();
+        runtime.block_on(async move {
+            time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(starts.load(Ordering::SeqCst), 2);
+
+            // kill the node
+            let sleeper = node1.spawn(time::sleep(Duration::from_secs(10)));
+            let err = node1.spawn(async { panic!("boom") }).await.unwrap_err();
+            assert!(err.is_panic());
+            assert_eq!(
+                err.to_string(),
+                format!("task {} panicked with message \"boom\"", err.id())
+            );
+            assert_eq!(*err.into_panic().downcast::<&str>().unwrap(), "boom");
+            assert!(sleeper.await.unwrap_err().is_cancelled());
+            assert_eq!(starts.load(Ordering::SeqCst), 2);
+
+            // restart the node
+            let err = node2.spawn(async { panic!("boom") }).await.unwrap_err();
+            assert!(err.is_panic());
+            time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(starts.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn panic_propagate() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        runtime.block_on(async move {
+            let _ = node.spawn(async { panic!("boom") }).await;
+        });
+    }
+
+    #[test]
+    fn consume_cpu() {
+        let runtime = Runtime::new();
+        let elapsed = |node: crate::runtime::NodeHandle| {
+            let f = node.spawn(async {
+                let time = time::TimeHandle::current();
+                let t0 = time.elapsed();
+                let tasks: Vec<_> = (0..4)
+                    .map(|_| spawn(super::consume_cpu(Duration::from_secs(1))))
+                    .collect();
+                futures::future::join_all(tasks).await;
+                time.elapsed() - t0
+            });
+            runtime.block_on(f).unwrap()
+        };
+        let secs = |d: Duration| d.as_secs_f64().round() as u64;
+        assert_eq!(secs(elapsed(runtime.create_node().build())), 4);
+        assert_eq!(secs(elapsed(runtime.create_node().cores(2).build())), 2);
+        assert_eq!(secs(elapsed(runtime.create_node().cores(8).build())), 1);
+    }
+
+    #[test]
+    fn consume_cpu_abort() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let f = node.spawn(async {
+            let time = time::TimeHandle::current();
+            let t0 = time.elapsed();
+            let t1 = spawn(super::consume_cpu(Duration::from_secs(10)));
+            let t2 = spawn(super::consume_cpu(Duration::from_secs(10)));
+            time::sleep(Duration::from_secs(1)).await;
+            // the core is released when the running task is aborted
+            t1.abort();
+            t2.await.unwrap();
+            time.elapsed() - t0
+        });
+        let elapsed = runtime.block_on(f).unwrap();
+        assert_eq!(elapsed.as_secs_f64().round(), 11.0);
+    }
+
+    #[test]
+    fn spawn_blocking_pool() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().max_blocking_threads(2).build();
+        let f = node.spawn(async {
+            let time = time::TimeHandle::current();
+            let t0 = time.elapsed();
+            let tasks: Vec<_> = (0..4)
+                .map(|i| spawn_blocking_with_cost(Duration::from_secs(1), move || i))
+                .collect();
+            // other tasks keep running while the blocking calls are in progress
+            let ticker = spawn(async move {
+                time::sleep(Duration::from_millis(500)).await;
+                time::TimeHandle::current().elapsed() - t0
+            });
+            let outputs = futures::future::join_all(tasks).await;
+            assert_eq!(
+                outputs.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+                [0, 1, 2, 3]
+            );
+            assert!(ticker.await.unwrap() < Duration::from_secs(1));
+            time.elapsed() - t0
+        });
+        let elapsed = runtime.block_on(f).unwrap();
+        assert_eq!(elapsed.as_secs_f64().round(), 2.0);
+    }
+
+    #[test]
+    fn spawn_blocking_abort() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().max_blocking_threads(1).build();
+        let f = node.spawn(async {
+            let t0 = time::TimeHandle::current().elapsed();
+            let t1 = spawn_blocking_with_cost(Duration::from_secs(10), || ());
+            // a call without cost still waits for an idle thread
+            let t2 = spawn_blocking(|| time::TimeHandle::current().elapsed());
+            time::sleep(Duration::from_secs(1)).await;
+            // the thread is released when the running call is aborted
+            t1.abort();
+            t2.await.unwrap() - t0
+        });
+        let elapsed = runtime.block_on(f).unwrap();
+        assert_eq!(elapsed.as_secs_f64().round(), 1.0);
+    }
+
+    #[test]
+    fn builder() {
+        let spawn_ids = || {
+            let runtime = Runtime::new();
+            let node = runtime.create_node().name("server").build();
+            runtime.block_on(async move {
+                let t1 = Builder::new().name("worker").spawn(async {
+                    let meta = crate::context::try_current_task_meta().unwrap();
+                    assert_eq!(meta.name.as_deref(), Some("worker"));
+                    assert_eq!(meta.info.name, "main");
+                });
+                let t2 = Builder::new().name("remote").spawn_on(
+                    async {
+                        let meta = crate::context::try_current_task_meta().unwrap();
+                        assert_eq!(meta.name.as_deref(), Some("remote"));
+                        assert_eq!(meta.info.name, "server");
+                    },
+                    &node,
+                );
+                let ids = (t1.id, t2.id);
+                t1.await.unwrap();
+                t2.await.unwrap();
+                ids
+            })
+        };
+        // task IDs are allocated per runtime
+        assert_eq!(spawn_ids(), spawn_ids());
+    }
+
+    #[test]
+    fn dump_tasks() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().name("server").build();
+        let location = format!("{}:{}:", file!(), line!() + 1);
+        let task = node.spawn(std::future::pending::<()>());
+        runtime.block_on(async move {
+            time::sleep(Duration::from_secs(1)).await;
+            let dump = crate::context::current(|h| h.task.spawner.dump_tasks());
+            assert!(dump.contains("\"server\""), "{dump}");
+            assert!(dump.contains(&location), "{dump}");
+
+            task.abort();
+            time::sleep(Duration::from_secs(1)).await;
+            let dump = crate::context::current(|h| h.task.spawner.dump_tasks());
+            assert!(!dump.contains(&location), "{dump}");
+        });
+    }
+
+    #[test]
+    fn step_wise() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().build();
+        let flag = Arc::new(AtomicUsize::new(0));
+        let flag1 = flag.clone();
+        node.spawn(async move {
+            time::sleep(Duration::from_secs(2)).await;
+            flag1.fetch_add(1, Ordering::SeqCst);
+            time::sleep(Duration::from_secs(2)).await;
+            flag1.fetch_add(1, Ordering::SeqCst);
+        });
+        let start = runtime.now();
+        assert_eq!(runtime.next_timer_deadline(), None);
+        assert_eq!(runtime.runnable_tasks()[&node.id()], 1);
+
+        runtime.run_until_idle();
+        // no timer has fired
+        assert!(runtime.now() - start < Duration::from_micros(1));
+        let deadline = runtime.next_timer_deadline().unwrap();
+        assert_eq!(deadline - start, Duration::from_secs(2));
+        assert_eq!(runtime.runnable_tasks()[&node.id()], 0);
+
+        // freeze the world at 3s
+        runtime.run_until(start + Duration::from_secs(3));
+        assert_eq!(runtime.now(), start + Duration::from_secs(3));
+        assert_eq!(flag.load(Ordering::SeqCst), 1);
+
+        // the runtime can be driven by `block_on` afterwards
+        runtime.block_on(async { time::sleep(Duration::from_secs(2)).await });
+        assert_eq!(flag.load(Ordering::SeqCst), 2);
+        assert!(!runtime.step());
+    }
+
+    #[test]
+    fn break_at() {
+        let runtime = Runtime::new();
+        let node = runtime.create_node().name("server").build();
+        node.spawn(async { time::sleep(Duration::from_secs(10)).await });
+        let hits = Arc::new(Mutex::new(vec![]));
+        for secs in [3, 1, 2] {
+            let hits = hits.clone();
+            runtime
+                .handle()
+                .break_at(Duration::from_secs(secs), move || {
+                    let now = Handle::current().time.elapsed();
+                    let dump = crate::context::current(|h| h.task.spawner.dump_tasks());
+                    hits.lock().push((secs, now, dump));
+                });
+        }
+        let start = runtime.now();
+        runtime.run_until(start + Duration::from_millis(2500));
+        let hits = hits.lock();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].0, 1);
+        assert_eq!(hits[1].0, 2);
+        assert!(hits[0].1 >= Duration::from_secs(1));
+        assert!(hits[0].2.contains("\"server\""));
+    }
+
+    #[test]
+    fn dump_state() {
+        let executor = Executor::new(GlobalRng::new_with_seed(0), Box::<FifoScheduler>::default());
+        let handle = executor.handle();
+        let node1 = handle.create_node(
+            Some("server".into()),
+            None,
+            None,
+            None,
+            PanicPolicy::default(),
+        );
+        let node2 = handle.create_node(None, None, None, None, PanicPolicy::default());
+        node1.spawn(async {});
+        handle.pause(node2.id());
+        let dump = executor.dump_state();
+        assert!(
+            dump.starts_with("nodes:\n  Node(0) \"main\": running"),
+            "{dump}"
+        );
+        assert!(
+            dump.contains("Node(1) \"server\": running, 1 runnable task(s)\n"),
+            "{dump}"
+        );
+        assert!(
+            dump.contains("Node(2) \"node-2\": paused, 0 runnable task(s)\n"),
+            "{dump}"
+        );
+        assert!(dump.contains("live tasks:\n"), "{dump}");
+    }
+
+    #[test]
+    fn node_status() {
+        let runtime = Runtime::new();
+        let handle = runtime.handle();
+        let node = runtime.create_node().name("server").build();
+        runtime.create_node().name("server").build();
+        assert_eq!(node.name(), "server");
+        assert_eq!(handle.node_by_name("server").unwrap().id(), node.id());
+        assert_eq!(handle.nodes().len(), 2);
+
+        assert_eq!(node.status(), NodeStatus::Running);
+        handle.pause(node.id());
+        assert_eq!(node.status(), NodeStatus::Paused);
+        handle.resume(node.id());
+        assert_eq!(node.status(), NodeStatus::Running);
+        handle.kill(node.id());
+        assert_eq!(node.status(), NodeStatus::Killed);
+        // pausing or resuming a killed node doesn't bring it back
+        handle.pause(node.id());
+        assert_eq!(node.status(), NodeStatus::Killed);
+        handle.resume(node.id());
+        assert_eq!(node.status(), NodeStatus::Killed);
+        handle.restart(node.id());
+        assert_eq!(node.status(), NodeStatus::Running);
+
+        // handles obtained inside the node share the status
+        let node = handle.get_node(node.id()).unwrap();
+        let inner = node.spawn(async { TaskNodeHandle::current() });
+        let inner = runtime.block_on(inner).unwrap();
+        handle.kill(node.id());
+        assert_eq!(inner.status(), NodeStatus::Killed);
+    }
+}
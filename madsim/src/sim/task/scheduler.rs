@@ -0,0 +1,434 @@
+//! Scheduling strategies of the task executor.
+
+use super::Id;
+use crate::rand::{GlobalRng, Rng};
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+};
+
+/// A strategy to decide which ready task to run next.
+///
+/// The executor pushes every task that becomes runnable into the scheduler,
+/// and pops one task at a time to poll. All randomness must be drawn from the
+/// given [`GlobalRng`] to keep the simulation deterministic.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+pub trait Scheduler: Send + 'static {
+    /// Add a runnable task to the ready queue.
+    fn push(&mut self, task: Id);
+
+    /// Remove a task from the ready queue and return it.
+    ///
+    /// Returns `None` if the ready queue is empty.
+    fn pop(&mut self, rng: &GlobalRng) -> Option<Id>;
+
+    /// Called when a task finishes or is dropped.
+    ///
+    /// The task will never be pushed again, so any state kept for it can be released.
+    fn remove(&mut self, task: Id) {
+        let _ = task;
+    }
+}
+
+/// Pick the next task uniformly at random.
+///
+/// This is the default scheduler.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default)]
+pub struct RandomScheduler {
+    ready: Vec<Id>,
+}
+
+impl Scheduler for RandomScheduler {
+    fn push(&mut self, task: Id) {
+        self.ready.push(task);
+    }
+
+    fn pop(&mut self, rng: &GlobalRng) -> Option<Id> {
+        if self.ready.is_empty() {
+            return None;
+        }
+        let idx = rng.with(|rng| rng.gen_range(0..self.ready.len()));
+        Some(self.ready.swap_remove(idx))
+    }
+}
+
+/// Run tasks in the order they become ready.
+///
+/// This scheduler never consumes random numbers, which is useful for debugging.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default)]
+pub struct FifoScheduler {
+    ready: VecDeque<Id>,
+}
+
+impl Scheduler for FifoScheduler {
+    fn push(&mut self, task: Id) {
+        self.ready.push_back(task);
+    }
+
+    fn pop(&mut self, _rng: &GlobalRng) -> Option<Id> {
+        self.ready.pop_front()
+    }
+}
+
+/// Probabilistic concurrency testing (PCT).
+///
+/// Each task is assigned a random priority when it first becomes ready, and
+/// the ready task with the highest priority always runs. At `depth - 1` random
+/// change points within the first `max_steps` steps, the priority of the
+/// running task is lowered below all initial priorities.
+///
+/// A bug that requires `d` ordering constraints between events is found with
+/// probability at least `1 / (n * k^(d-1))`, where `n` is the number of tasks
+/// and `k` is the number of steps.
+///
+/// Ref: <https://www.microsoft.com/en-us/research/publication/a-randomized-scheduler-with-probabilistic-guarantees-of-finding-bugs/>
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug)]
+pub struct PctScheduler {
+    depth: u32,
+    max_steps: u64,
+    step: u64,
+    /// Sorted steps at which the priority of the running task is lowered.
+    change_points: Option<Vec<u64>>,
+    priorities: HashMap<Id, u64>,
+    ready: Vec<Id>,
+}
+
+impl PctScheduler {
+    /// Create a PCT scheduler with the given bug depth and estimated number of steps.
+    pub fn new(depth: u32, max_steps: u64) -> Self {
+        assert_ne!(depth, 0, "depth must be greater than 0");
+        assert_ne!(max_steps, 0, "max_steps must be greater than 0");
+        PctScheduler {
+            depth,
+            max_steps,
+            step: 0,
+            change_points: None,
+            priorities: HashMap::new(),
+            ready: Vec::new(),
+        }
+    }
+}
+
+impl Scheduler for PctScheduler {
+    fn push(&mut self, task: Id) {
+        self.ready.push(task);
+    }
+
+    fn pop(&mut self, rng: &GlobalRng) -> Option<Id> {
+        if self.ready.is_empty() {
+            return None;
+        }
+        let (depth, max_steps) = (self.depth as u64, self.max_steps);
+        let change_points = self.change_points.get_or_insert_with(|| {
+            let mut points: Vec<u64> = (1..depth)
+                .map(|_| rng.with(|rng| rng.gen_range(0..max_steps)))
+                .collect();
+            points.sort_unstable();
+            points
+        });
+        // assign priorities to new tasks
+        for task in &self.ready {
+            if !self.priorities.contains_key(task) {
+                let priority = rng.with(|rng| rng.gen_range(depth..u64::MAX));
+                self.priorities.insert(*task, priority);
+            }
+        }
+        let (idx, _) = (self.ready.iter().enumerate())
+            .max_by_key(|(_, task)| self.priorities[task])
+            .unwrap();
+        let task = self.ready.swap_remove(idx);
+        // lower the priority at change points
+        if let Some(i) = change_points.iter().position(|&p| p == self.step) {
+            self.priorities.insert(task, depth - 1 - i as u64);
+        }
+        self.step += 1;
+        Some(task)
+    }
+
+    fn remove(&mut self, task: Id) {
+        self.priorities.remove(&task);
+    }
+}
+
+/// Configuration of the task scheduler.
+///
+/// It can be parsed from a string like `random`, `fifo` or `pct:depth=3,steps=10000`.
+/// In the config file, the arguments can also be given as a table like
+/// `{ pct = { depth = 3 } }`.
+#[cfg_attr(docsrs, doc(cfg(madsim)))]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum SchedulerConfig {
+    /// See [`RandomScheduler`].
+    #[default]
+    Random,
+    /// See [`FifoScheduler`].
+    Fifo,
+    /// See [`PctScheduler`].
+    Pct {
+        /// The bug depth.
+        depth: u32,
+        /// The estimated number of steps.
+        steps: u64,
+    },
+}
+
+const fn default_pct_depth() -> u32 {
+    3
+}
+
+const fn default_pct_steps() -> u64 {
+    10000
+}
+
+/// Serialize as a string, because the TOML serializer doesn't support struct variants.
+impl Serialize for SchedulerConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SchedulerConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SchedulerVisitor)
+    }
+}
+
+/// The arguments of [`SchedulerConfig::Pct`] in a table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PctArgs {
+    #[serde(default = "default_pct_depth")]
+    depth: u32,
+    #[serde(default = "default_pct_steps")]
+    steps: u64,
+}
+
+struct SchedulerVisitor;
+
+impl<'de> Visitor<'de> for SchedulerVisitor {
+    type Value = SchedulerConfig;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a scheduler like \"random\", or a table like `{ pct = { depth = 3 } }`")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let config = match map.next_key::<String>()?.as_deref() {
+            Some("pct") => {
+                let args = map.next_value::<PctArgs>()?;
+                SchedulerConfig::Pct {
+                    depth: args.depth,
+                    steps: args.steps,
+                }
+            }
+            Some(name) => return Err(de::Error::custom(format!("unknown scheduler: {name:?}"))),
+            None => return Err(de::Error::custom("expect a scheduler")),
+        };
+        if map.next_key::<String>()?.is_some() {
+            return Err(de::Error::custom("expect only one scheduler"));
+        }
+        Ok(config)
+    }
+}
+
+impl SchedulerConfig {
+    /// Build a scheduler from the configuration.
+    pub fn build(&self) -> Box<dyn Scheduler> {
+        match *self {
+            Self::Random => Box::<RandomScheduler>::default(),
+            Self::Fifo => Box::<FifoScheduler>::default(),
+            Self::Pct { depth, steps } => Box::new(PctScheduler::new(depth, steps)),
+        }
+    }
+}
+
+impl FromStr for SchedulerConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, args) = s.split_once(':').unwrap_or((s, ""));
+        let mut config = match name {
+            "random" => Self::Random,
+            "fifo" => Self::Fifo,
+            "pct" => Self::Pct {
+                depth: default_pct_depth(),
+                steps: default_pct_steps(),
+            },
+            _ => return Err(format!("unknown scheduler: {name:?}")),
+        };
+        for arg in args.split(',').filter(|s| !s.is_empty()) {
+            let (key, value) = (arg.split_once('='))
+                .ok_or_else(|| format!("invalid scheduler argument: {arg:?}"))?;
+            let invalid = |_| format!("invalid value of {key:?}: {value:?}");
+            match (&mut config, key) {
+                (Self::Pct { depth, .. }, "depth") => *depth = value.parse().map_err(invalid)?,
+                (Self::Pct { steps, .. }, "steps") => *steps = value.parse().map_err(invalid)?,
+                _ => return Err(format!("unknown argument {key:?} for scheduler {name:?}")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl fmt::Display for SchedulerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Random => write!(f, "random"),
+            Self::Fifo => write!(f, "fifo"),
+            Self::Pct { depth, steps } => write!(f, "pct:depth={depth},steps={steps}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime::Runtime, task::spawn, Config};
+    use std::{
+        collections::HashSet,
+        panic::AssertUnwindSafe,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    #[test]
+    fn parse_config() {
+        assert_eq!("random".parse(), Ok(SchedulerConfig::Random));
+        assert_eq!("fifo".parse(), Ok(SchedulerConfig::Fifo));
+        assert_eq!(
+            "pct:depth=5".parse(),
+            Ok(SchedulerConfig::Pct {
+                depth: 5,
+                steps: 10000
+            })
+        );
+        let config: SchedulerConfig = "pct:depth=2,steps=100".parse().unwrap();
+        assert_eq!(config.to_string().parse(), Ok(config));
+        assert!("fifo:depth=2".parse::<SchedulerConfig>().is_err());
+        assert!("pct:depth=x".parse::<SchedulerConfig>().is_err());
+        assert!("lifo".parse::<SchedulerConfig>().is_err());
+
+        // print and parse the config file
+        let config = Config {
+            scheduler: SchedulerConfig::Pct {
+                depth: 2,
+                steps: 100,
+            },
+            ..Default::default()
+        };
+        assert_eq!(config.to_string().parse::<Config>().unwrap(), config);
+    }
+
+    fn run_sequence(seed: u64, scheduler: SchedulerConfig) -> Vec<i32> {
+        let config = Config {
+            scheduler,
+            ..Default::default()
+        };
+        let runtime = Runtime::with_seed_and_config(seed, config);
+        runtime.block_on(async {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let mut tasks = vec![];
+            for i in 0..3 {
+                let tx = tx.clone();
+                tasks.push(spawn(async move {
+                    for j in 0..5 {
+                        tx.send(i * 10 + j).unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                }));
+            }
+            drop(tx);
+            futures::future::join_all(tasks).await;
+            rx.into_iter().collect::<Vec<_>>()
+        })
+    }
+
+    #[test]
+    fn fifo() {
+        let seqs = (0..5)
+            .map(|seed| run_sequence(seed, SchedulerConfig::Fifo))
+            .collect::<HashSet<_>>();
+        assert_eq!(seqs.len(), 1);
+        let seq = seqs.into_iter().next().unwrap();
+        assert_eq!(
+            seq,
+            vec![0, 10, 20, 1, 11, 21, 2, 12, 22, 3, 13, 23, 4, 14, 24]
+        );
+    }
+
+    #[test]
+    fn pct() {
+        let config = SchedulerConfig::Pct {
+            depth: 3,
+            steps: 20,
+        };
+        let seqs = (0..10)
+            .map(|seed| run_sequence(seed, config))
+            .collect::<HashSet<_>>();
+        assert!(seqs.len() > 1);
+        // the same seed always produces the same sequence
+        assert_eq!(run_sequence(1, config), run_sequence(1, config));
+    }
+
+    #[test]
+    fn pct_release_finished() {
+        let mut scheduler = PctScheduler::new(3, 100);
+        let rng = GlobalRng::new_with_seed(1);
+        for i in 0..10 {
+            scheduler.push(Id(i));
+        }
+        while let Some(task) = scheduler.pop(&rng) {
+            scheduler.remove(task);
+        }
+        assert!(scheduler.priorities.is_empty());
+    }
+
+    #[test]
+    fn set_scheduler_with_queued_tasks() {
+        let runtime = Runtime::new();
+        let done = Arc::new(AtomicUsize::new(0));
+        let done1 = done.clone();
+        // a panic stops the executor with other tasks in the ready queue
+        let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(async move {
+                for _ in 0..10 {
+                    let done = done1.clone();
+                    spawn(async move {
+                        for _ in 0..10 {
+                            tokio::task::yield_now().await;
+                        }
+                        done.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+                tokio::task::yield_now().await;
+                panic!("boom");
+            })
+        }));
+        assert!(res.is_err());
+        assert!(done.load(Ordering::SeqCst) < 10);
+        runtime.set_scheduler(FifoScheduler::default());
+        runtime.block_on(async move {
+            for _ in 0..1000 {
+                if done.load(Ordering::SeqCst) == 10 {
+                    return;
+                }
+                tokio::task::yield_now().await;
+            }
+            panic!("queued tasks are lost");
+        });
+    }
+}
@@ -2,9 +2,11 @@
 //!
 //!
 
-use crate::rand::{GlobalRng, Rng};
+use crate::{
+    determinism::Event,
+    rand::{GlobalRng, Rng},
+};
 use futures::{select_biased, FutureExt};
-use naive_timer::Timer;
 use spin::Mutex;
 #[doc(no_inline)]
 pub use std::time::{Duration, Instant};
@@ -14,12 +16,15 @@ pub mod error;
 mod interval;
 mod sleep;
 mod system_time;
+mod timer;
 
 pub use self::interval::{interval, interval_at, Interval, MissedTickBehavior};
 pub use self::sleep::{sleep, sleep_until, Sleep};
+use self::timer::Timer;
 
 pub(crate) struct TimeRuntime {
     handle: TimeHandle,
+    rand: GlobalRng,
 }
 
 impl TimeRuntime {
@@ -34,7 +39,10 @@ impl TimeRuntime {
             timer: Arc::new(Mutex::new(Timer::default())),
             clock: ClockHandle::new(base_time),
         };
-        TimeRuntime { handle }
+        TimeRuntime {
+            handle,
+            rand: rand.clone(),
+        }
     }
 
     pub fn handle(&self) -> &TimeHandle {
@@ -51,7 +59,12 @@ impl TimeRuntime {
             //       t0 + (t1 - t0) < t1 !!
             // we should add eps to make sure 'now >= deadline' and avoid deadlock
             time += Duration::from_nanos(50);
-            timer.expire(time);
+            // time may have been advanced beyond the timer by blocking calls
+            let time = time.max(self.handle.clock.elapsed());
+            let recorder = self.rand.recorder();
+            recorder.timer(time);
+            self.rand.log_event(|| Event::Timer);
+            timer.expire(time, recorder);
             self.handle.clock.set_elapsed(time);
             true
         } else {
@@ -64,7 +77,12 @@ impl TimeRuntime {
         self.handle.clock.advance(duration);
     }
 
-    #[allow(dead_code)]
+    /// Returns the time of the closest timer event.
+    pub fn next_event_instant(&self) -> Option<Instant> {
+        let next = self.handle.timer.lock().next()?;
+        Some(self.handle.clock.base_instant() + next)
+    }
+
     /// Get the current time.
     pub fn now_instant(&self) -> Instant {
         self.handle.now_instant()
@@ -104,6 +122,11 @@ impl TimeHandle {
         self.clock.elapsed()
     }
 
+    /// Advances time immediately.
+    pub(crate) fn advance(&self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+
     /// Waits until `duration` has elapsed.
     pub fn sleep(&self, duration: Duration) -> Sleep {
         self.sleep_until(self.clock.now_instant() + duration)
@@ -139,7 +162,7 @@ impl TimeHandle {
         callback: impl FnOnce() + Send + Sync + 'static,
     ) {
         let mut timer = self.timer.lock();
-        timer.add(deadline - self.clock.base_instant(), |_| callback());
+        timer.add(deadline - self.clock.base_instant(), callback);
     }
 
     pub(crate) fn add_timer(&self, dur: Duration, callback: impl FnOnce() + Send + Sync + 'static) {
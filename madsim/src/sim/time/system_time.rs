@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 /// Override the libc `gettimeofday` function. For `SystemTime` on macOS.
 #[no_mangle]
 #[inline(never)]
@@ -46,16 +48,17 @@ unsafe extern "C" fn clock_gettime(
     if let Some(time) = super::TimeHandle::try_current() {
         // inside a madsim context, use the simulated time.
         if clockid == 0 {
-            let dur = time
-                .now_time()
-                .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                .unwrap();
+            let dur = realtime_now(&time);
             tp.write(libc::timespec {
                 tv_sec: dur.as_secs() as _,
                 tv_nsec: dur.subsec_nanos() as _,
             });
         } else if clockid == 1 {
-            tp.write(std::mem::transmute(time.now_instant()));
+            let dur = monotonic_now(&time);
+            tp.write(libc::timespec {
+                tv_sec: dur.as_secs() as _,
+                tv_nsec: dur.subsec_nanos() as _,
+            });
         } else {
             panic!("unsupported clockid: {}", clockid);
         };
@@ -75,6 +78,124 @@ unsafe extern "C" fn clock_gettime(
     }
 }
 
+/// Returns the reading of `CLOCK_REALTIME` in the simulation.
+#[cfg(target_os = "linux")]
+fn realtime_now(time: &super::TimeHandle) -> Duration {
+    time.now_time()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+}
+
+/// Returns the reading of `CLOCK_MONOTONIC` in the simulation.
+///
+/// `Instant` is a reading of `CLOCK_MONOTONIC`, so this is the simulated instant
+/// since the zero instant.
+///
+/// NOTE: don't transmute `Instant` into `timespec`, their layouts may differ.
+#[cfg(target_os = "linux")]
+fn monotonic_now(time: &super::TimeHandle) -> Duration {
+    // SAFETY: a zeroed `Instant` is the reading 0 of the clock.
+    let zero: std::time::Instant = unsafe { std::mem::zeroed() };
+    time.now_instant().duration_since(zero)
+}
+
+/// Override the libc `nanosleep` function. For `std::thread::sleep`.
+///
+/// Inside a madsim context, the simulated time is advanced instead of blocking
+/// the thread. Like a real blocking call, no other task can run in the meantime.
+#[no_mangle]
+#[inline(never)]
+unsafe extern "C" fn nanosleep(
+    req: *const libc::timespec,
+    rem: *mut libc::timespec,
+) -> libc::c_int {
+    // NOTE: invalid arguments are passed to the original function to set errno.
+    if let (Some(time), Some(dur)) = (super::TimeHandle::try_current(), to_duration(&*req)) {
+        // inside a madsim context, advance the simulated time.
+        time.advance(dur);
+        if !rem.is_null() {
+            rem.write(libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            });
+        }
+        0
+    } else {
+        lazy_static::lazy_static! {
+            static ref NANOSLEEP: unsafe extern "C" fn(
+                req: *const libc::timespec,
+                rem: *mut libc::timespec,
+            ) -> libc::c_int = unsafe {
+                let ptr = libc::dlsym(libc::RTLD_NEXT, b"nanosleep\0".as_ptr() as _);
+                assert!(!ptr.is_null());
+                std::mem::transmute(ptr)
+            };
+        }
+        NANOSLEEP(req, rem)
+    }
+}
+
+/// Converts a `timespec` into `Duration`. Returns `None` if it is invalid.
+fn to_duration(ts: &libc::timespec) -> Option<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as _, ts.tv_nsec as _))
+}
+
+/// Override the libc `clock_nanosleep` function. For Linux.
+///
+/// Inside a madsim context, the simulated time is advanced instead of blocking
+/// the thread.
+#[no_mangle]
+#[inline(never)]
+#[cfg(target_os = "linux")]
+unsafe extern "C" fn clock_nanosleep(
+    clockid: libc::clockid_t,
+    flags: libc::c_int,
+    req: *const libc::timespec,
+    rem: *mut libc::timespec,
+) -> libc::c_int {
+    if let Some(time) = super::TimeHandle::try_current() {
+        // inside a madsim context, advance the simulated time.
+        let req = match to_duration(&*req) {
+            Some(req) => req,
+            None => return libc::EINVAL,
+        };
+        let dur = if flags & libc::TIMER_ABSTIME == 0 {
+            req
+        } else if clockid == 0 {
+            req.saturating_sub(realtime_now(&time))
+        } else if clockid == 1 {
+            req.saturating_sub(monotonic_now(&time))
+        } else {
+            return libc::EINVAL;
+        };
+        time.advance(dur);
+        if !rem.is_null() && flags & libc::TIMER_ABSTIME == 0 {
+            rem.write(libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            });
+        }
+        0
+    } else {
+        lazy_static::lazy_static! {
+            static ref CLOCK_NANOSLEEP: unsafe extern "C" fn(
+                clockid: libc::clockid_t,
+                flags: libc::c_int,
+                req: *const libc::timespec,
+                rem: *mut libc::timespec,
+            ) -> libc::c_int = unsafe {
+                let ptr = libc::dlsym(libc::RTLD_NEXT, b"clock_nanosleep\0".as_ptr() as _);
+                assert!(!ptr.is_null());
+                std::mem::transmute(ptr)
+            };
+        }
+        CLOCK_NANOSLEEP(clockid, flags, req, rem)
+    }
+}
+
 /// Override the `mach_absolute_time` function. For `Instant` on macOS.
 #[no_mangle]
 #[inline(never)]
@@ -99,6 +220,7 @@ extern "C" fn mach_absolute_time() -> u64 {
 #[cfg(test)]
 mod tests {
     use crate::runtime::Runtime;
+    use crate::time::TimeHandle;
     use std::collections::BTreeSet;
     use std::time::{Duration, Instant, SystemTime};
 
@@ -136,4 +258,68 @@ mod tests {
         }
         assert_eq!(times.len(), 1);
     }
+
+    #[test]
+    fn std_instant_is_simulated_instant() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let time = TimeHandle::current();
+            assert_eq!(Instant::now(), time.now_instant());
+            crate::time::sleep(Duration::from_secs(1)).await;
+            assert_eq!(Instant::now(), time.now_instant());
+        });
+    }
+
+    #[test]
+    fn std_thread_sleep() {
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let time = TimeHandle::current();
+            let t0 = time.elapsed();
+            std::thread::sleep(Duration::from_secs(10));
+            assert_eq!(time.elapsed() - t0, Duration::from_secs(10));
+            // timers are still fired in order
+            crate::time::sleep(Duration::from_secs(1)).await;
+            assert!(time.elapsed() - t0 >= Duration::from_secs(11));
+        });
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn clock_nanosleep_abstime() {
+        fn clock_now(clockid: libc::clockid_t) -> Duration {
+            let mut tp = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            assert_eq!(unsafe { libc::clock_gettime(clockid, &mut tp) }, 0);
+            Duration::new(tp.tv_sec as _, tp.tv_nsec as _)
+        }
+        fn sleep_until(clockid: libc::clockid_t, deadline: Duration) {
+            let req = libc::timespec {
+                tv_sec: deadline.as_secs() as _,
+                tv_nsec: deadline.subsec_nanos() as _,
+            };
+            let ret = unsafe {
+                libc::clock_nanosleep(clockid, libc::TIMER_ABSTIME, &req, std::ptr::null_mut())
+            };
+            assert_eq!(ret, 0);
+        }
+
+        let runtime = Runtime::new();
+        runtime.block_on(async {
+            let time = TimeHandle::current();
+            for clockid in [libc::CLOCK_MONOTONIC, libc::CLOCK_REALTIME] {
+                let t0 = time.elapsed();
+                let deadline = clock_now(clockid) + Duration::from_secs(3);
+                sleep_until(clockid, deadline);
+                assert_eq!(time.elapsed() - t0, Duration::from_secs(3));
+                assert_eq!(clock_now(clockid), deadline);
+
+                // a deadline in the past returns immediately
+                sleep_until(clockid, deadline - Duration::from_secs(1));
+                assert_eq!(time.elapsed() - t0, Duration::from_secs(3));
+            }
+        });
+    }
 }
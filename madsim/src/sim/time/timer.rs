@@ -0,0 +1,123 @@
+//! A timer queue ordered by deadline.
+
+use crate::replay::Recorder;
+use std::{cmp::Ordering, collections::BinaryHeap, time::Duration};
+
+type Callback = Box<dyn FnOnce() + Send + Sync + 'static>;
+
+/// A timer queue.
+///
+/// Timers expiring at the same time fire in the order they were added,
+/// unless a replayed trace decides otherwise.
+#[derive(Default)]
+pub(crate) struct Timer {
+    events: BinaryHeap<Event>,
+    next_seq: u64,
+}
+
+struct Event {
+    deadline: Duration,
+    seq: u64,
+    callback: Callback,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed to make the heap pop the earliest event first
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+impl Timer {
+    /// Add a timer event which fires at `deadline`.
+    pub fn add(&mut self, deadline: Duration, callback: impl FnOnce() + Send + Sync + 'static) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.events.push(Event {
+            deadline,
+            seq,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Returns the deadline of the earliest event.
+    pub fn next(&self) -> Option<Duration> {
+        self.events.peek().map(|e| e.deadline)
+    }
+
+    /// Fire all events whose deadline is not after `now`.
+    ///
+    /// The order of events with the same deadline is recorded by `recorder`.
+    pub fn expire(&mut self, now: Duration, recorder: &Recorder) {
+        while let Some(deadline) = self.next() {
+            if deadline > now {
+                break;
+            }
+            let mut ties = vec![];
+            while matches!(self.events.peek(), Some(e) if e.deadline == deadline) {
+                ties.push(self.events.pop().unwrap());
+            }
+            while !ties.is_empty() {
+                let idx = if ties.len() == 1 {
+                    0
+                } else {
+                    let seq =
+                        recorder.fire(|seq| ties.iter().any(|e| e.seq == seq), || ties[0].seq);
+                    ties.iter().position(|e| e.seq == seq).unwrap()
+                };
+                (ties.remove(idx).callback)();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::Trace;
+    use std::sync::{Arc, Mutex};
+
+    fn fire_all(recorder: &Recorder) -> Vec<u32> {
+        let fired = Arc::new(Mutex::new(vec![]));
+        let mut timer = Timer::default();
+        for (i, ms) in [(0, 2), (1, 1), (2, 2), (3, 2)] {
+            let fired = fired.clone();
+            timer.add(Duration::from_millis(ms), move || {
+                fired.lock().unwrap().push(i)
+            });
+        }
+        timer.expire(Duration::from_millis(1), recorder);
+        assert_eq!(timer.next(), Some(Duration::from_millis(2)));
+        timer.expire(Duration::from_millis(2), recorder);
+        assert_eq!(timer.next(), None);
+        let fired = fired.lock().unwrap().clone();
+        fired
+    }
+
+    #[test]
+    fn ties() {
+        let recorder = Recorder::default();
+        recorder.enable_record();
+        assert_eq!(fire_all(&recorder), [1, 0, 2, 3]);
+        let trace = recorder.take_trace().unwrap();
+        assert_eq!(trace.to_string(), "fire 0\nfire 2\n");
+
+        // replay a different order of timers expiring at the same time
+        recorder.enable_replay("fire 3\nfire 0\n".parse::<Trace>().unwrap());
+        assert_eq!(fire_all(&recorder), [1, 3, 0, 2]);
+    }
+}
@@ -1,8 +1,6 @@
-//! A multi-producer, single-consumer queue but allows
-//! consumer to randomly choose an element from the queue.
+//! A multi-producer, single-consumer queue that allows
+//! consumer to take all elements from the queue at once.
 
-use crate::rand::GlobalRng;
-use rand::Rng;
 use spin::Mutex;
 use std::{fmt, sync::Arc};
 
@@ -61,24 +59,16 @@ impl<T> Sender<T> {
     }
 }
 
-/// This enumeration is the list of the possible reasons
-/// that `try_recv_random` could not return data when called.
-pub enum TryRecvError {
-    Empty,
-    Disconnected,
-}
-
 impl<T> Receiver<T> {
-    /// Attempts to return a pending value on this receiver without blocking.
-    pub fn try_recv_random(&self, rng: &GlobalRng) -> Result<T, TryRecvError> {
-        let mut queue = self.inner.queue.lock();
-        if !queue.is_empty() {
-            let idx = rng.with(|rng| rng.gen_range(0..queue.len()));
-            Ok(queue.swap_remove(idx))
-        } else if Arc::weak_count(&self.inner) == 0 {
-            Err(TryRecvError::Disconnected)
-        } else {
-            Err(TryRecvError::Empty)
-        }
+    /// Takes all pending values on this receiver without blocking.
+    ///
+    /// Values are returned in the order they were sent.
+    pub fn try_recv_all(&self) -> Vec<T> {
+        std::mem::take(&mut *self.inner.queue.lock())
+    }
+
+    /// Calls `f` with all pending values on this receiver without taking them.
+    pub fn peek_all<R>(&self, f: impl FnOnce(&[T]) -> R) -> R {
+        f(&self.inner.queue.lock())
     }
 }
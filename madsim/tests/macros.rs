@@ -0,0 +1,131 @@
+//! Tests of the arguments of `#[madsim::test]`.
+
+#![cfg(madsim)]
+
+use madsim::{
+    net::Endpoint,
+    runtime::Handle,
+    time::{sleep, Duration, Instant},
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+};
+
+#[madsim::test(seed = 42)]
+async fn seed() {
+    assert_eq!(Handle::current().seed(), 42);
+}
+
+#[madsim::test(seed = 1, count = 2, jobs = 2)]
+async fn jobs() {
+    // both seeds must be running at the same time
+    static STARTED: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+    let (started, cond) = &STARTED;
+    *started.lock().unwrap() += 1;
+    cond.notify_all();
+    let (_started, timeout) = cond
+        .wait_timeout_while(
+            started.lock().unwrap(),
+            std::time::Duration::from_secs(10),
+            |n| *n < 2,
+        )
+        .unwrap();
+    assert!(!timeout.timed_out(), "seeds are not run in parallel");
+}
+
+#[madsim::test(time_limit = "1s")]
+#[should_panic(expected = "time limit exceeded")]
+async fn time_limit() {
+    sleep(Duration::from_secs(2)).await;
+}
+
+#[madsim::test(time_limit = "1.5")]
+async fn time_limit_in_seconds() {
+    sleep(Duration::from_secs(1)).await;
+}
+
+#[madsim::test(config = "tests/macros.toml")]
+async fn config() {
+    let handle = Handle::current();
+    let addr1 = "10.0.0.1:1".parse::<SocketAddr>().unwrap();
+    let addr2 = "10.0.0.2:1".parse::<SocketAddr>().unwrap();
+    let node1 = handle.create_node().ip(addr1.ip()).build();
+    let node2 = handle.create_node().ip(addr2.ip()).build();
+    let receiver = node2.spawn(async move {
+        let ep = Endpoint::bind(addr2).await.unwrap();
+        let mut buf = [0; 1];
+        ep.recv_from(1, &mut buf).await.unwrap();
+    });
+    let t0 = Instant::now();
+    node1
+        .spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            let ep = Endpoint::bind(addr1).await.unwrap();
+            ep.send_to(addr2, 1, &[1]).await.unwrap();
+        })
+        .await
+        .unwrap();
+    receiver.await.unwrap();
+    // `send_latency` is set to 5s in the config
+    assert!(t0.elapsed() >= Duration::from_secs(6));
+}
+
+#[madsim::test(check_determinism)]
+async fn check_determinism() {
+    sleep(Duration::from_millis(madsim::rand::random::<u64>() % 100)).await;
+}
+
+#[madsim::test(check_determinism)]
+#[should_panic(expected = "non-determinism detected")]
+async fn check_determinism_fails() {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    if RUNS.fetch_add(1, Ordering::Relaxed) == 1 {
+        madsim::rand::random::<u64>();
+    }
+    sleep(Duration::from_secs(1)).await;
+}
+
+#[madsim::test]
+#[allow(clippy::unused_unit)]
+async fn returns_unit() -> () {}
+
+#[madsim::test]
+async fn returns_result() -> Result<(), std::io::Error> {
+    sleep(Duration::from_secs(1)).await;
+    Ok(())
+}
+
+// run by `returns_error`, as a test with `#[should_panic]` must return `()`
+#[madsim::test]
+#[ignore]
+async fn returns_error_inner() -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "boom"))
+}
+
+#[test]
+#[should_panic(expected = "`returns_error_inner` returned an error")]
+fn returns_error() {
+    let _ = returns_error_inner();
+}
+
+#[madsim::main]
+async fn main_returns_error() -> std::io::Result<()> {
+    sleep(Duration::from_secs(1)).await;
+    Err(std::io::Error::new(std::io::ErrorKind::Other, "boom"))
+}
+
+#[test]
+fn main_returns_error_as_is() {
+    let err = main_returns_error().unwrap_err();
+    assert_eq!(err.to_string(), "boom");
+}
+
+#[madsim::test]
+async fn returns_exit_code() -> std::process::ExitCode {
+    sleep(Duration::from_secs(1)).await;
+    std::process::ExitCode::SUCCESS
+}